@@ -180,7 +180,7 @@ var getPieceValue = function (piece, x, y) {
 /* board visualization and games state handling */
 
 var onDragStart = function (source, piece, position, orientation) {
-    if (game.in_checkmate() === true ⠵⠵⠺⠞⠟⠺⠞⠵⠺⠺⠵⠟⠵⠟⠟⠵⠞⠟⠵⠵⠺⠵⠞⠵⠺
+    if (game.in_checkmate() === true || game.in_draw() === true ||
         piece.search(/^b/) !== -1) {
         return false;
     }
@@ -0,0 +1,520 @@
+//! Headless batch analysis of JavaScript files.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use halstead_metrics::*;
+
+const USAGE: &str = "\
+Usage: halstead_cli [OPTIONS] <FILE|DIR>...
+
+Computes Halstead, Djilb, McCabe, cognitive complexity and Chepin metrics of every
+given JavaScript or TypeScript file, directories are searched recursively
+for .js, .mjs, .cjs and .ts files. TypeScript declaration files, .d.ts,
+are not supported and skipped there, named files of any other extension
+are parsed as scripts unless `--source` says otherwise.
+
+Options:
+    -f, --format <FORMAT>  Output format, `table` (default), `tsv`, `json`,
+                           `html`, `markdown` or `sarif`. The JSON document of
+                           every file and function is described by
+                           schema/analysis.schema.json, the HTML report is a
+                           single offline page, the Markdown table sums up the
+                           main metrics for pull request comments, the SARIF
+                           2.1.0 log lists the metrics above their `--max`.
+    --baseline <FILE>      JSON output of an earlier run, the Markdown table
+                           shows the changes since it, only with
+                           `--format markdown`
+    --max <METRIC>=<VALUE> Threshold of a metric of files and functions, by
+                           its key in the `tsv` header, like `effort=5000` or
+                           `djilb_max_if_depth=3`, may be repeated, the last
+                           one of a metric wins, only with `--format sarif`
+    --theme <THEME>        Syntect theme of the HTML report, `InspiredGitHub`
+                           (default), `Solarized (light)`, `Solarized (dark)`,
+                           `base16-ocean.light`, `base16-ocean.dark`,
+                           `base16-mocha.dark` or `base16-eighties.dark`
+    -F, --functions        Also report every function and class separately
+    -O, --occurrences      Instead of the metrics, list every counted operator
+                           and operand with its location, as TSV
+    -E, --explain          Instead of the metrics, list every counted Djilb's
+                           statement with its location and why, as TSV
+    --source <KIND>        Parse every file as `script`, `module` or
+                           `typescript`, instead of by its extension: .mjs
+                           files are modules, .ts TypeScript, others scripts
+    --rules <FILE>         Counting rules profile, TOML or JSON, see
+                           rules/default.toml
+    --by-name              Count all variables of the same name as one, instead
+                           of telling apart every declared variable
+    --chepin-weights <P,M,C,T>
+                           Weights of Chepin's classes, `1,2,3,0.5` by default
+    --json-schema          Print the JSON Schema of the `json` format
+    -h, --help             Print this help
+
+Syntax errors are printed to stderr as `file:line:column: message` and
+counted in the `Errs` column, metrics of code with any may be off.
+Exits with 1 if any of the files could not be read or parsed.";
+
+#[derive(Clone, Copy)]
+enum Format {
+    Table,
+    Tsv,
+    Json,
+    #[cfg(feature = "html")]
+    Html,
+    Markdown,
+    Sarif,
+}
+
+impl Format {
+    /// Formats written at once after all the files, rather than row by row.
+    fn is_document(self) -> bool {
+        match self {
+            Format::Table | Format::Tsv => false,
+            Format::Json | Format::Markdown | Format::Sarif => true,
+            #[cfg(feature = "html")]
+            Format::Html => true,
+        }
+    }
+}
+
+struct Args {
+    format: Format,
+    functions: bool,
+    occurrences: bool,
+    explain: bool,
+    #[cfg(feature = "html")]
+    theme: html::SyntectTheme,
+    /// Loaded along with the files, not an error of the arguments.
+    baseline: Option<PathBuf>,
+    thresholds: Vec<sarif::Threshold>,
+    options: AnalysisOptions,
+    /// Source kind of all the files, by their extensions if `None`.
+    source_kind: Option<SourceKind>,
+    paths: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut format = Format::Table;
+    let mut functions = false;
+    let mut occurrences = false;
+    let mut explain = false;
+    #[cfg(feature = "html")]
+    let mut theme = html::SyntectTheme::InspiredGitHub;
+    let mut baseline = None;
+    let mut thresholds = Vec::new();
+    let mut options = AnalysisOptions::default();
+    let mut source_kind = None;
+    let mut paths = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            "--json-schema" => {
+                print!("{}", export::JSON_SCHEMA);
+                std::process::exit(0);
+            }
+            "-f" | "--format" => {
+                format = match args.next().as_deref() {
+                    Some("table") => Format::Table,
+                    Some("tsv") => Format::Tsv,
+                    Some("json") => Format::Json,
+                    #[cfg(feature = "html")]
+                    Some("html") => Format::Html,
+                    Some("markdown") => Format::Markdown,
+                    Some("sarif") => Format::Sarif,
+                    Some(other) => return Err(format!("unknown format `{other}`")),
+                    None => return Err("missing value for --format".to_string()),
+                }
+            }
+            "-F" | "--functions" => functions = true,
+            "-O" | "--occurrences" => occurrences = true,
+            "-E" | "--explain" => {
+                explain = true;
+                options.explain = true;
+            }
+            #[cfg(feature = "html")]
+            "--theme" => {
+                let value = args.next().ok_or("missing value for --theme")?;
+                theme = html::SyntectTheme::all()
+                    .find(|theme| theme.syntect_key_name() == value)
+                    .ok_or_else(|| format!("unknown theme `{value}`"))?;
+            }
+            "--baseline" => {
+                let path = args.next().ok_or("missing value for --baseline")?;
+                baseline = Some(PathBuf::from(path));
+            }
+            "--max" => {
+                let value = args.next().ok_or("missing value for --max")?;
+                thresholds.push(value.parse().map_err(|err| format!("{err}"))?);
+            }
+            "--by-name" => options.identifier_keys = IdentifierKeys::Name,
+            "--source" => {
+                source_kind = Some(match args.next().as_deref() {
+                    Some("script") => SourceKind::Script,
+                    Some("module") => SourceKind::Module,
+                    Some("typescript") => SourceKind::TypeScript,
+                    Some(other) => return Err(format!("unknown source kind `{other}`")),
+                    None => return Err("missing value for --source".to_string()),
+                })
+            }
+            "--rules" => {
+                let path = args.next().ok_or("missing value for --rules")?;
+                options.rules = CountingRules::load(Path::new(&path))
+                    .map_err(|err| format!("{path}: {err}"))?;
+            }
+            "--chepin-weights" => {
+                let value = args.next().ok_or("missing value for --chepin-weights")?;
+                options.chepin_weights = parse_weights(&value)
+                    .ok_or_else(|| format!("invalid Chepin weights `{value}`"))?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("no input files".to_string());
+    }
+    if baseline.is_some() && !matches!(format, Format::Markdown) {
+        return Err("--baseline only applies to `--format markdown`".to_string());
+    }
+    if !thresholds.is_empty() && !matches!(format, Format::Sarif) {
+        return Err("--max only applies to `--format sarif`".to_string());
+    }
+    Ok(Args {
+        format,
+        functions,
+        occurrences,
+        explain,
+        #[cfg(feature = "html")]
+        theme,
+        baseline,
+        thresholds,
+        options,
+        source_kind,
+        paths,
+    })
+}
+
+/// Parses `P,M,C,T` weights.
+fn parse_weights(value: &str) -> Option<ChepinWeights> {
+    let weights: Vec<f32> = value
+        .split(',')
+        .map(|w| w.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match weights[..] {
+        [p, m, c, t] => Some(ChepinWeights { p, m, c, t }),
+        _ => None,
+    }
+}
+
+/// Expands directories into the JavaScript files they contain.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_owned());
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            collect_files(&entry, files)?;
+        } else if SourceKind::of_path(&entry).is_some() {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Syntax errors in the function, or in the whole file.
+fn parse_errors_in(dict: &Dictionary, function: Option<&FunctionMetrics>) -> usize {
+    dict.parse_errors
+        .iter()
+        .filter(|e| function.is_none_or(|f| (f.span.start..f.span.end).contains(&e.span.start)))
+        .count()
+}
+
+/// Name of a table row, the file itself or one of its functions.
+fn row_name(file: &Path, function: Option<&FunctionMetrics>) -> String {
+    match function {
+        None => file.display().to_string(),
+        Some(f) => format!("  {} ({}:{})", f.name, f.span.line, f.span.column),
+    }
+}
+
+fn print_table_header() {
+    println!(
+        "{:<40} {:>6} {:>6} {:>10} {:>8} {:>12} {:>7} {:>6} {:>4} {:>6} {:>4} {:>4} {:>4} {:>7} {:>4}",
+        "File", "n", "N", "V", "D", "E", "B", "Stmts", "CL", "cl", "CLI", "CC", "Cog", "Q", "Errs"
+    );
+}
+
+fn print_table_row(name: &str, report: &MetricsReport, parse_errors: usize) {
+    let h = &report.halstead;
+    let d = &report.djilb;
+    println!(
+        "{:<40} {:>6} {:>6} {:>10.2} {:>8.2} {:>12.2} {:>7.3} {:>6} {:>4} {:>6.3} {:>4} {:>4} {:>4} {:>7.1} {:>4}",
+        name,
+        h.vocabulary,
+        h.length,
+        h.volume,
+        h.difficulty,
+        h.effort,
+        h.delivered_bugs,
+        d.statements,
+        d.if_count,
+        d.if_saturation,
+        d.max_if_depth,
+        report.mccabe.cyclomatic_complexity,
+        report.cognitive_complexity,
+        report.chepin.q,
+        parse_errors,
+    );
+}
+
+fn print_tsv_header() {
+    let keys: Vec<_> = MetricsReport::default()
+        .rows()
+        .iter()
+        .map(|row| row.key)
+        .collect();
+    println!(
+        "file\tfunction\tline\tcolumn\tparse_errors\t{}",
+        keys.join("\t")
+    );
+}
+
+fn print_tsv_row(
+    file: &Path,
+    function: Option<&FunctionMetrics>,
+    report: &MetricsReport,
+    parse_errors: usize,
+) {
+    let values: Vec<_> = report
+        .rows()
+        .iter()
+        .map(|row| row.value.to_string())
+        .collect();
+    let (name, line, column) = match function {
+        None => (String::new(), String::new(), String::new()),
+        Some(f) => (
+            f.name.clone(),
+            f.span.line.to_string(),
+            f.span.column.to_string(),
+        ),
+    };
+    println!(
+        "{}\t{name}\t{line}\t{column}\t{parse_errors}\t{}",
+        file.display(),
+        values.join("\t")
+    );
+}
+
+/// Escapes the characters which would break a TSV field.
+fn tsv_field(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn print_occurrences_header() {
+    println!("file\tkind\ttoken\tline\tcolumn\tend_line\tend_column\tstart\tend");
+}
+
+fn print_occurrences(file: &Path, dict: &Dictionary) {
+    let mut occurrences: Vec<_> = [
+        ("operator", &dict.operator_spans),
+        ("operand", &dict.operand_spans),
+    ]
+    .into_iter()
+    .flat_map(|(kind, spans)| {
+        spans
+            .iter()
+            .flat_map(move |(token, spans)| spans.iter().map(move |span| (span, kind, token)))
+    })
+    .collect();
+    occurrences.sort_by_key(|(span, kind, _)| (span.start, *kind));
+
+    for (span, kind, token) in occurrences {
+        println!(
+            "{}\t{kind}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            file.display(),
+            tsv_field(token),
+            span.line,
+            span.column,
+            span.end_line,
+            span.end_column,
+            span.start,
+            span.end
+        );
+    }
+}
+
+fn print_explanations_header() {
+    println!("file\tline\tcolumn\tend_line\tend_column\tstatements\treason");
+}
+
+fn print_explanations(file: &Path, dict: &Dictionary) {
+    for explanation in &dict.explanations {
+        let span = explanation.span;
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            file.display(),
+            span.line,
+            span.column,
+            span.end_line,
+            span.end_column,
+            explanation.statements,
+            tsv_field(&explanation.reason)
+        );
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let baseline = match &args.baseline {
+        None => None,
+        Some(path) => match markdown::Baseline::load(path) {
+            Ok(baseline) => Some(baseline),
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+
+    let mut files = Vec::new();
+    let mut failed = false;
+    for path in &args.paths {
+        if let Err(err) = collect_files(path, &mut files) {
+            eprintln!("{}: {err}", path.display());
+            failed = true;
+        }
+    }
+
+    match args.format {
+        _ if args.occurrences => print_occurrences_header(),
+        _ if args.explain => print_explanations_header(),
+        Format::Table => print_table_header(),
+        Format::Tsv => print_tsv_header(),
+        _ => {}
+    }
+
+    let mut options = args.options.clone();
+    /* JSON, HTML, Markdown and SARIF documents are written at once, after all the files */
+    let mut analyzed = Vec::new();
+    for file in &files {
+        options.source_kind = args
+            .source_kind
+            .or_else(|| SourceKind::of_path(file))
+            .unwrap_or_default();
+        let source = match std::fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {err}", file.display());
+                failed = true;
+                continue;
+            }
+        };
+
+        let dict = match process_js_with(&source, &options) {
+            Ok(dict) => dict,
+            Err(err) => {
+                eprintln!("{}: {err}", file.display());
+                failed = true;
+                continue;
+            }
+        };
+        for err in &dict.parse_errors {
+            eprintln!("{}:{err}", file.display());
+            failed = true;
+        }
+
+        if args.occurrences {
+            print_occurrences(file, &dict);
+            continue;
+        }
+        if args.explain {
+            print_explanations(file, &dict);
+            continue;
+        }
+        if args.format.is_document() {
+            analyzed.push((file.display().to_string(), source, dict));
+            continue;
+        }
+
+        let mut rows = vec![(None, &dict.report)];
+        if args.functions {
+            rows.extend(dict.functions.iter().map(|f| (Some(f), &f.dict.report)));
+        }
+        for (function, report) in rows {
+            let parse_errors = parse_errors_in(&dict, function);
+            match args.format {
+                Format::Table => print_table_row(&row_name(file, function), report, parse_errors),
+                Format::Tsv => print_tsv_row(file, function, report, parse_errors),
+                _ => unreachable!("documents are written after all the files"),
+            }
+        }
+    }
+
+    match args.format {
+        _ if args.occurrences || args.explain => {}
+        Format::Json => println!(
+            "{}",
+            export::to_json(
+                analyzed
+                    .iter()
+                    .map(|(path, _, dict)| (Some(path.as_str()), dict))
+            )
+        ),
+        #[cfg(feature = "html")]
+        Format::Html => {
+            let files = analyzed
+                .iter()
+                .map(|(path, source, dict)| html::ReportFile {
+                    name: path,
+                    source,
+                    dict,
+                });
+            print!("{}", html::to_html("Complexity report", files, args.theme));
+        }
+        Format::Markdown => print!(
+            "{}",
+            markdown::to_markdown(
+                analyzed.iter().map(|(path, _, dict)| (path.as_str(), dict)),
+                baseline.as_ref(),
+                args.functions,
+            )
+        ),
+        Format::Sarif => println!(
+            "{}",
+            sarif::to_sarif(
+                analyzed.iter().map(|(path, _, dict)| (path.as_str(), dict)),
+                &args.thresholds,
+            )
+        ),
+        Format::Table | Format::Tsv => {}
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,549 @@
+//! Machine readable export of analyses.
+//!
+//! The JSON document is described by `schema/analysis.schema.json`, its
+//! `schema_version` is bumped whenever a field is removed or changes its
+//! meaning. New fields may appear without a bump.
+//!
+//! CSV tables follow RFC 4180: a header row, CRLF line ends, and fields with
+//! commas, quotes or line breaks quoted.
+
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::metrics::{ChepinType, Dictionary, FunctionMetrics, IdentProperties, ParseError};
+use crate::report::{ChepinGroups, MetricValue, MetricsReport};
+use crate::span::Span;
+
+/// Version of the JSON document, the `schema_version` field.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Identifier of the schema, the `$schema` field of the JSON document.
+pub const SCHEMA_ID: &str = "urn:halstead-metrics:analysis:1";
+
+/// JSON Schema of the JSON document.
+pub const JSON_SCHEMA: &str = include_str!("../schema/analysis.schema.json");
+
+#[derive(Serialize)]
+struct Document<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    schema_version: u32,
+    files: Vec<File<'a>>,
+}
+
+#[derive(Serialize)]
+struct File<'a> {
+    /// `None` for code typed into the GUI.
+    path: Option<&'a str>,
+    parse_errors: &'a [ParseError],
+    #[serde(flatten)]
+    analysis: Analysis<'a>,
+    functions: Vec<Function<'a>>,
+}
+
+#[derive(Serialize)]
+struct Function<'a> {
+    name: &'a str,
+    kind: &'static str,
+    span: Span,
+    #[serde(flatten)]
+    analysis: Analysis<'a>,
+}
+
+/// What is exported of the whole file and of every function alike.
+#[derive(Serialize)]
+struct Analysis<'a> {
+    metrics: Map<String, Value>,
+    chepin: Chepin<'a>,
+    operators: Vec<Token<'a>>,
+    operands: Vec<Token<'a>>,
+    identifiers: Vec<Identifier<'a>>,
+}
+
+#[derive(Serialize)]
+struct Chepin<'a> {
+    all: &'a ChepinGroups,
+    io: &'a ChepinGroups,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Token<'a> {
+    pub name: &'a str,
+    pub count: usize,
+    pub occurrences: &'a [Span],
+}
+
+#[derive(Serialize)]
+struct Identifier<'a> {
+    name: &'a str,
+    class: &'static str,
+    /// Identifier span, unlike the `span` locations elsewhere.
+    span_count: usize,
+    io: bool,
+    used_in: &'a [String],
+}
+
+/// Metrics by their machine readable key. Floats are written as they are
+/// displayed, `0.1` rather than `0.10000000149011612`.
+fn metrics(report: &MetricsReport) -> Map<String, Value> {
+    report
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let value = match row.value {
+                MetricValue::Int(n) => Value::from(n),
+                MetricValue::Float(x) => x
+                    .to_string()
+                    .parse()
+                    .ok()
+                    .and_then(Number::from_f64)
+                    .map_or(Value::Null, Value::Number),
+            };
+            (row.key.to_string(), value)
+        })
+        .collect()
+}
+
+/// Tokens from the most frequent one, then by name.
+pub(crate) fn tokens<'a>(
+    counts: &'a HashMap<String, usize>,
+    spans: &'a HashMap<String, Vec<Span>>,
+) -> Vec<Token<'a>> {
+    let mut tokens: Vec<_> = counts
+        .iter()
+        .map(|(name, &count)| Token {
+            name,
+            count,
+            occurrences: spans.get(name).map_or(&[], Vec::as_slice),
+        })
+        .collect();
+    tokens.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(b.name)));
+    tokens
+}
+
+fn identifiers(identifiers: &HashMap<String, IdentProperties>) -> Vec<Identifier<'_>> {
+    let mut identifiers: Vec<_> = identifiers
+        .iter()
+        .map(|(name, props)| Identifier {
+            name,
+            class: match props.ctype {
+                ChepinType::P => "P",
+                ChepinType::M => "M",
+                ChepinType::C => "C",
+                ChepinType::T => "T",
+            },
+            span_count: props.spen,
+            io: props.io,
+            used_in: &props.used_in,
+        })
+        .collect();
+    identifiers.sort_by(|a, b| a.name.cmp(b.name));
+    identifiers
+}
+
+fn analysis(dict: &Dictionary) -> Analysis<'_> {
+    Analysis {
+        metrics: metrics(&dict.report),
+        chepin: Chepin {
+            all: &dict.report.chepin.all,
+            io: &dict.report.chepin.io,
+        },
+        operators: tokens(&dict.operators, &dict.operator_spans),
+        operands: tokens(&dict.operands, &dict.operand_spans),
+        identifiers: identifiers(&dict.identifiers),
+    }
+}
+
+fn function(function: &FunctionMetrics) -> Function<'_> {
+    Function {
+        name: &function.name,
+        kind: function.kind.name(),
+        span: function.span,
+        analysis: analysis(&function.dict),
+    }
+}
+
+/// JSON document of the analyses of the files, `None` paths stand for code
+/// which is not in a file.
+pub fn to_json<'a>(files: impl IntoIterator<Item = (Option<&'a str>, &'a Dictionary)>) -> String {
+    let document = Document {
+        schema: SCHEMA_ID,
+        schema_version: SCHEMA_VERSION,
+        files: files
+            .into_iter()
+            .map(|(path, dict)| File {
+                path,
+                parse_errors: &dict.parse_errors,
+                analysis: analysis(dict),
+                functions: dict.functions.iter().map(function).collect(),
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&document).expect("Analysis is serializable")
+}
+
+/// Quotes the field if it has a comma, a quote or a line break in it.
+pub fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// CSV table with a header row.
+struct Csv(String);
+
+impl Csv {
+    fn new(header: &[&str]) -> Self {
+        let mut csv = Self(String::new());
+        csv.record(header);
+        csv
+    }
+
+    fn record<S: AsRef<str>>(&mut self, fields: &[S]) {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                self.0.push(',');
+            }
+            self.0.push_str(&csv_field(field.as_ref()));
+        }
+        self.0.push_str("\r\n");
+    }
+}
+
+/// Name of the function of a row, empty for the whole file.
+fn function_columns(function: Option<&FunctionMetrics>) -> [String; 3] {
+    match function {
+        None => Default::default(),
+        Some(f) => [
+            f.name.clone(),
+            f.span.line.to_string(),
+            f.span.column.to_string(),
+        ],
+    }
+}
+
+/// CSV tables of the analysis by their file names, `operators.csv`,
+/// `operands.csv`, `metrics.csv` of the file and every function,
+/// `identifiers.csv` and `occurrences.csv`. Every row starts with the
+/// `source` file.
+pub fn to_csv(source: &str, dict: &Dictionary) -> Vec<(&'static str, String)> {
+    let mut files = Vec::new();
+
+    for (file, kind, counts, spans) in [
+        (
+            "operators.csv",
+            "operator",
+            &dict.operators,
+            &dict.operator_spans,
+        ),
+        (
+            "operands.csv",
+            "operand",
+            &dict.operands,
+            &dict.operand_spans,
+        ),
+    ] {
+        let mut csv = Csv::new(&["source", kind, "count"]);
+        for token in tokens(counts, spans) {
+            csv.record(&[source, token.name, &token.count.to_string()]);
+        }
+        files.push((file, csv.0));
+    }
+
+    let mut csv = Csv::new(&["source", "function", "line", "column", "metric", "value"]);
+    let rows = std::iter::once((None, &dict.report))
+        .chain(dict.functions.iter().map(|f| (Some(f), &f.dict.report)));
+    for (function, report) in rows {
+        let [name, line, column] = function_columns(function);
+        for row in report.rows() {
+            csv.record(&[
+                source,
+                &name,
+                &line,
+                &column,
+                row.key,
+                &row.value.to_string(),
+            ]);
+        }
+    }
+    files.push(("metrics.csv", csv.0));
+
+    let mut csv = Csv::new(&[
+        "source",
+        "identifier",
+        "class",
+        "span_count",
+        "io",
+        "used_in",
+    ]);
+    for ident in identifiers(&dict.identifiers) {
+        csv.record(&[
+            source,
+            ident.name,
+            ident.class,
+            &ident.span_count.to_string(),
+            &ident.io.to_string(),
+            &ident.used_in.join(" "),
+        ]);
+    }
+    files.push(("identifiers.csv", csv.0));
+
+    let mut csv = Csv::new(&[
+        "source",
+        "kind",
+        "token",
+        "line",
+        "column",
+        "end_line",
+        "end_column",
+        "start",
+        "end",
+    ]);
+    let mut occurrences: Vec<_> = [
+        ("operator", &dict.operator_spans),
+        ("operand", &dict.operand_spans),
+    ]
+    .into_iter()
+    .flat_map(|(kind, spans)| {
+        spans
+            .iter()
+            .flat_map(move |(token, spans)| spans.iter().map(move |span| (span, kind, token)))
+    })
+    .collect();
+    occurrences.sort_by_key(|(span, kind, _)| (span.start, *kind));
+    for (span, kind, token) in occurrences {
+        let numbers = [
+            span.line,
+            span.column,
+            span.end_line,
+            span.end_column,
+            span.start,
+            span.end,
+        ]
+        .map(|n| n.to_string());
+        let mut record = vec![source, kind, token.as_str()];
+        record.extend(numbers.iter().map(String::as_str));
+        csv.record(&record);
+    }
+    files.push(("occurrences.csv", csv.0));
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::process_js;
+
+    /// Checks `value` against the part of JSON Schema the schema uses,
+    /// `$ref`, `type`, `const`, `enum`, `minimum`, `required`, `properties`
+    /// and `items`, with the path of the first mismatch as the error.
+    fn validate(schema: &Value, root: &Value, value: &Value, path: &str) -> Result<(), String> {
+        let fail = |what: &str| Err(format!("{path}: {what}"));
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.strip_prefix("#/$defs/").unwrap();
+            validate(&root["$defs"][name], root, value, path)?;
+        }
+        if let Some(types) = schema.get("type") {
+            let types: Vec<_> = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                _ => types.as_str().into_iter().collect(),
+            };
+            let is = |ty: &&str| match *ty {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "number" => value.is_number(),
+                "integer" => value.is_u64() || value.is_i64(),
+                "boolean" => value.is_boolean(),
+                "null" => value.is_null(),
+                _ => panic!("unknown type {ty}"),
+            };
+            if !types.iter().any(is) {
+                return fail(&format!("{value} is not {types:?}"));
+            }
+        }
+        if schema
+            .get("const")
+            .is_some_and(|expected| expected != value)
+        {
+            return fail(&format!("{value} is not {}", schema["const"]));
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            if !values.contains(value) {
+                return fail(&format!("{value} is not one of {values:?}"));
+            }
+        }
+        if let (Some(minimum), Some(n)) = (schema.get("minimum"), value.as_f64()) {
+            if n < minimum.as_f64().unwrap() {
+                return fail(&format!("{n} is below {minimum}"));
+            }
+        }
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for key in required.iter().filter_map(Value::as_str) {
+                if value.get(key).is_none() {
+                    return fail(&format!("misses `{key}`"));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, property) in properties {
+                if let Some(field) = value.get(key) {
+                    validate(property, root, field, &format!("{path}.{key}"))?;
+                }
+            }
+        }
+        if let (Some(items), Some(values)) = (schema.get("items"), value.as_array()) {
+            for (i, item) in values.iter().enumerate() {
+                validate(items, root, item, &format!("{path}[{i}]"))?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn json_export() {
+        let dict = process_js("function f(a) { return a + 1; }\nvar s = `x, \"y\"\nz`;").unwrap();
+        let json: Value = serde_json::from_str(&to_json([(Some("a.js"), &dict)])).unwrap();
+        let schema: Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+
+        assert_eq!(schema["$id"], SCHEMA_ID);
+        validate(&schema, &schema, &json, "$").unwrap();
+
+        /* Every metric of the report is required, and nothing else */
+        let mut required: Vec<_> = schema["$defs"]["metrics"]["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|key| key.as_str().unwrap())
+            .collect();
+        required.sort_unstable();
+        let mut keys: Vec<_> = dict.report.rows().iter().map(|row| row.key).collect();
+        keys.sort_unstable();
+        assert_eq!(required, keys);
+
+        /* A document without what the schema requires is caught */
+        let mut broken = json.clone();
+        broken["files"][0]["functions"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("metrics");
+        assert_eq!(
+            validate(&schema, &schema, &broken, "$"),
+            Err("$.files[0].functions[0]: misses `metrics`".to_string())
+        );
+
+        let file = &json["files"][0];
+        assert_eq!(file["path"], "a.js");
+        assert_eq!(file["metrics"]["cyclomatic_complexity"], 2);
+
+        /* Operands with commas, quotes and newlines survive */
+        let chunk = file["operands"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|od| od["name"] == "x, \"y\"\nz")
+            .unwrap();
+        assert_eq!(chunk["count"], 1);
+        assert_eq!(chunk["occurrences"][0]["line"], 2);
+
+        let function = &file["functions"][0];
+        assert_eq!(function["name"], "f");
+        assert_eq!(function["kind"], "function");
+        assert_eq!(function["identifiers"][0]["name"], "a");
+        assert_eq!(function["identifiers"][0]["class"], "P");
+        assert!(function["identifiers"][0]["span_count"].is_u64());
+    }
+
+    /// Records of an RFC 4180 table, every one ended by CRLF.
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut chars = text.chars().peekable();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => record.push(std::mem::take(&mut field)),
+                '\r' if !quoted => {
+                    assert_eq!(chars.next(), Some('\n'), "records end with CRLF");
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                _ => field.push(c),
+            }
+        }
+        assert!(field.is_empty() && record.is_empty(), "unterminated record");
+        records
+    }
+
+    #[test]
+    fn csv_export() {
+        assert_eq!(csv_field("a b"), "a b");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+
+        let dict = process_js("function f(a) { return a; }\nvar s = `x, \"y\"\nz`;").unwrap();
+        let source = "dir, 1/a.js";
+        let files = to_csv(source, &dict);
+        let tables: HashMap<_, _> = files
+            .iter()
+            .map(|(name, csv)| (*name, parse_csv(csv)))
+            .collect();
+        assert_eq!(tables.len(), 5);
+
+        /* Every record has as many fields as the header, source first */
+        for (name, records) in &tables {
+            let header = &records[0];
+            assert_eq!(header[0], "source", "{name}");
+            for record in &records[1..] {
+                assert_eq!(record.len(), header.len(), "{name}: {record:?}");
+                assert_eq!(record[0], source, "{name}");
+            }
+        }
+
+        let operands = &tables["operands.csv"];
+        assert_eq!(operands[0], ["source", "operand", "count"]);
+        assert!(operands.contains(&vec![
+            source.to_string(),
+            "x, \"y\"\nz".to_string(),
+            "1".to_string()
+        ]));
+
+        /* A row for every metric of the file and of `f` */
+        let metrics = &tables["metrics.csv"];
+        assert_eq!(
+            metrics[0],
+            ["source", "function", "line", "column", "metric", "value"]
+        );
+        let rows = dict.report.rows().len();
+        assert_eq!(metrics.len(), 1 + 2 * rows);
+        let value = |function: &str, metric: &str| {
+            metrics
+                .iter()
+                .find(|record| record[1] == function && record[4] == metric)
+                .map(|record| (record[2].as_str(), record[3].as_str(), record[5].as_str()))
+        };
+        assert_eq!(value("", "cyclomatic_complexity"), Some(("", "", "2")));
+        assert_eq!(value("f", "cyclomatic_complexity"), Some(("1", "1", "1")));
+
+        let identifiers = &tables["identifiers.csv"];
+        assert_eq!(identifiers[0][3], "span_count");
+        let s = identifiers.iter().find(|record| record[1] == "s").unwrap();
+        assert_eq!(s[2..], ["M", "0", "false", ""]);
+
+        /* One record for every occurrence of every token */
+        let occurrences: usize = dict.operators.values().chain(dict.operands.values()).sum();
+        assert_eq!(tables["occurrences.csv"].len(), 1 + occurrences);
+    }
+}
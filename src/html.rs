@@ -0,0 +1,486 @@
+//! Self-contained HTML report of analyses, readable without the GUI.
+//!
+//! The source is highlighted with the same syntect themes as the editor of
+//! the GUI, with every counted operator and operand marked.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, FontStyle, Style, Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::export::tokens;
+use crate::metrics::{ChepinType, Dictionary};
+use crate::report::{ChepinGroups, MetricsReport};
+use crate::span::Span;
+
+/// Syntect theme code is highlighted with.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum SyntectTheme {
+    Base16EightiesDark,
+    Base16MochaDark,
+    Base16OceanDark,
+    Base16OceanLight,
+    InspiredGitHub,
+    SolarizedDark,
+    SolarizedLight,
+}
+
+impl SyntectTheme {
+    pub fn all() -> impl ExactSizeIterator<Item = Self> {
+        [
+            Self::Base16EightiesDark,
+            Self::Base16MochaDark,
+            Self::Base16OceanDark,
+            Self::Base16OceanLight,
+            Self::InspiredGitHub,
+            Self::SolarizedDark,
+            Self::SolarizedLight,
+        ]
+        .iter()
+        .copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Base16EightiesDark => "Base16 Eighties (dark)",
+            Self::Base16MochaDark => "Base16 Mocha (dark)",
+            Self::Base16OceanDark => "Base16 Ocean (dark)",
+            Self::Base16OceanLight => "Base16 Ocean (light)",
+            Self::InspiredGitHub => "InspiredGitHub (light)",
+            Self::SolarizedDark => "Solarized (dark)",
+            Self::SolarizedLight => "Solarized (light)",
+        }
+    }
+
+    /// Name of the theme in syntect's default theme set.
+    pub fn syntect_key_name(&self) -> &'static str {
+        match self {
+            Self::Base16EightiesDark => "base16-eighties.dark",
+            Self::Base16MochaDark => "base16-mocha.dark",
+            Self::Base16OceanDark => "base16-ocean.dark",
+            Self::Base16OceanLight => "base16-ocean.light",
+            Self::InspiredGitHub => "InspiredGitHub",
+            Self::SolarizedDark => "Solarized (dark)",
+            Self::SolarizedLight => "Solarized (light)",
+        }
+    }
+
+    pub fn is_dark(&self) -> bool {
+        match self {
+            Self::Base16EightiesDark
+            | Self::Base16MochaDark
+            | Self::Base16OceanDark
+            | Self::SolarizedDark => true,
+
+            Self::Base16OceanLight | Self::InspiredGitHub | Self::SolarizedLight => false,
+        }
+    }
+}
+
+/// Analysis of a single file to report.
+pub struct ReportFile<'a> {
+    /// Path or any other name of the file.
+    pub name: &'a str,
+    /// Code the dictionary was computed from, spans are only valid for it.
+    pub source: &'a str,
+    /// With the properties computed.
+    pub dict: &'a Dictionary,
+}
+
+/// Metrics shown on the card of every function.
+const CARD_METRICS: [&str; 9] = [
+    "volume",
+    "difficulty",
+    "effort",
+    "statements",
+    "djilb_if_count",
+    "djilb_max_if_depth",
+    "cyclomatic_complexity",
+    "cognitive_complexity",
+    "chepin_q",
+];
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+h2 { border-bottom: 1px solid; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid rgba(128, 128, 128, 0.5); padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+td.number { text-align: right; font-family: monospace; }
+table.sortable th { cursor: pointer; }
+table.sortable th::after { content: ' \\2195'; opacity: 0.5; }
+pre.source { padding: 1em; overflow-x: auto; line-height: 1.4; border: 1px solid rgba(128, 128, 128, 0.5); }
+.operator { background: rgba(255, 140, 0, 0.28); }
+.operand { background: rgba(0, 150, 255, 0.28); }
+.legend span { padding: 0 0.4em; margin-right: 1em; font-family: monospace; }
+.errors { color: #d33; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; margin-bottom: 1em; }
+.card { border: 1px solid rgba(128, 128, 128, 0.5); border-radius: 4px; padding: 0.5em 1em; min-width: 14em; }
+.card h4 { margin: 0.2em 0; font-family: monospace; }
+.card dl { display: grid; grid-template-columns: auto auto; gap: 0 1em; margin: 0.5em 0; }
+.card dd { margin: 0; text-align: right; font-family: monospace; }
+";
+
+/// Sorts the tables by the clicked column, numbers by their value.
+const SCRIPT: &str = "
+document.querySelectorAll('table.sortable th').forEach(th => {
+  th.addEventListener('click', () => {
+    const table = th.closest('table');
+    const column = Array.from(th.parentNode.children).indexOf(th);
+    const ascending = th.dataset.order !== 'asc';
+    th.parentNode.querySelectorAll('th').forEach(other => delete other.dataset.order);
+    th.dataset.order = ascending ? 'asc' : 'desc';
+    const body = table.tBodies[0];
+    const key = row => row.children[column].dataset.value ?? row.children[column].textContent;
+    const rows = Array.from(body.rows).sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      const order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return ascending ? order : -order;
+    });
+    rows.forEach(row => body.appendChild(row));
+  });
+});
+";
+
+/// Escapes the text for HTML, attribute values included.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn css_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn css_style(style: &Style) -> String {
+    let mut css = format!("color:{}", css_color(style.foreground));
+    if style.font_style.contains(FontStyle::BOLD) {
+        css.push_str(";font-weight:bold");
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        css.push_str(";font-style:italic");
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        css.push_str(";text-decoration:underline");
+    }
+    css
+}
+
+/// Byte ranges of the source in the colors of the theme, `None` if syntect
+/// fails, then the source is left plain.
+fn highlight(
+    source: &str,
+    syntaxes: &SyntaxSet,
+    theme: &Theme,
+) -> Option<Vec<(Range<usize>, Style)>> {
+    /* TypeScript is not among the default syntaxes, JavaScript is close */
+    let syntax = syntaxes.find_syntax_by_extension("js")?;
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut styles = Vec::new();
+    let mut offset = 0;
+    for line in LinesWithEndings::from(source) {
+        for (style, piece) in highlighter.highlight_line(line, syntaxes).ok()? {
+            styles.push((offset..offset + piece.len(), style));
+            offset += piece.len();
+        }
+    }
+    Some(styles)
+}
+
+/// Source highlighted by the theme, with the innermost operator or operand
+/// occurrence at every character marked. Whitespace is left unmarked, so
+/// operators spanning a whole block, like `{}`, only mark what is not
+/// counted as anything else.
+fn annotated_source(
+    source: &str,
+    dict: &Dictionary,
+    syntaxes: &SyntaxSet,
+    theme: &Theme,
+) -> String {
+    let mut marks: Vec<(&Span, &str, &str)> = [
+        ("operator", &dict.operator_spans),
+        ("operand", &dict.operand_spans),
+    ]
+    .into_iter()
+    .flat_map(|(kind, spans)| {
+        spans.iter().flat_map(move |(token, spans)| {
+            spans.iter().map(move |span| (span, kind, token.as_str()))
+        })
+    })
+    .collect();
+    marks.sort_by_key(|(span, _, _)| std::cmp::Reverse(span.end - span.start));
+
+    let mut owner = vec![None; source.len()];
+    for (i, (span, _, _)) in marks.iter().enumerate() {
+        let end = span.end.min(source.len());
+        for byte in &mut owner[span.start.min(end)..end] {
+            *byte = Some(i);
+        }
+    }
+    for (byte, c) in source.bytes().enumerate() {
+        if c.is_ascii_whitespace() {
+            owner[byte] = None;
+        }
+    }
+
+    let plain = Style {
+        foreground: theme.settings.foreground.unwrap_or(Color::BLACK),
+        ..Default::default()
+    };
+    let styles =
+        highlight(source, syntaxes, theme).unwrap_or_else(|| vec![(0..source.len(), plain)]);
+
+    let mut html = String::new();
+    for (range, style) in styles {
+        let _ = write!(html, "<span style=\"{}\">", css_style(&style));
+        let mut start = range.start;
+        while start < range.end {
+            let mark = owner[start];
+            let mut end = start + 1;
+            while end < range.end && (owner[end] == mark || !source.is_char_boundary(end)) {
+                end += 1;
+            }
+            let text = escape(&source[start..end]);
+            match mark {
+                Some(i) => {
+                    let (_, kind, token) = marks[i];
+                    let _ = write!(
+                        html,
+                        "<span class=\"{kind}\" title=\"{kind} {}\">{text}</span>",
+                        escape(token)
+                    );
+                }
+                None => html.push_str(&text),
+            }
+            start = end;
+        }
+        html.push_str("</span>");
+    }
+    html
+}
+
+fn metrics_table(html: &mut String, report: &MetricsReport) {
+    html.push_str("<table>\n<thead><tr><th>Metric</th><th>Value</th></tr></thead>\n<tbody>\n");
+    for row in report.rows() {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"number\">{}</td></tr>",
+            escape(row.label),
+            row.value
+        );
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+fn function_cards(html: &mut String, dict: &Dictionary) {
+    if dict.functions.is_empty() {
+        return;
+    }
+    html.push_str("<h3>Functions</h3>\n<div class=\"cards\">\n");
+    for function in &dict.functions {
+        let _ = writeln!(
+            html,
+            "<div class=\"card\"><h4>{}</h4>{} at {}:{}\n<dl>",
+            escape(&function.name),
+            function.kind.name(),
+            function.span.line,
+            function.span.column
+        );
+        for row in function.dict.report.rows() {
+            if CARD_METRICS.contains(&row.key) {
+                let _ = writeln!(html, "<dt>{}</dt><dd>{}</dd>", escape(row.label), row.value);
+            }
+        }
+        html.push_str("</dl></div>\n");
+    }
+    html.push_str("</div>\n");
+}
+
+fn group(groups: &ChepinGroups, ctype: ChepinType) -> &[String] {
+    match ctype {
+        ChepinType::P => &groups.p,
+        ChepinType::M => &groups.m,
+        ChepinType::C => &groups.c,
+        ChepinType::T => &groups.t,
+    }
+}
+
+fn chepin_table(html: &mut String, dict: &Dictionary) {
+    let chepin = &dict.report.chepin;
+
+    html.push_str(
+        "<h3>Chepin</h3>\n<table>\n<thead><tr><th>Class</th><th>Count</th><th>Variables</th>\
+         <th>I/O count</th><th>I/O variables</th></tr></thead>\n<tbody>\n",
+    );
+    for (ctype, class) in [
+        (ChepinType::P, "P, input"),
+        (ChepinType::M, "M, modified"),
+        (ChepinType::C, "C, control"),
+        (ChepinType::T, "T, unused"),
+    ] {
+        let all = group(&chepin.all, ctype);
+        let io = group(&chepin.io, ctype);
+        let _ = writeln!(
+            html,
+            "<tr><td>{class}</td><td class=\"number\">{}</td><td>{}</td>\
+             <td class=\"number\">{}</td><td>{}</td></tr>",
+            all.len(),
+            escape(&all.join(", ")),
+            io.len(),
+            escape(&io.join(", "))
+        );
+    }
+    let _ = writeln!(
+        html,
+        "<tr><th>Q</th><td class=\"number\">{}</td><td></td><td class=\"number\">{}</td><td></td></tr>",
+        chepin.q, chepin.io_q
+    );
+    html.push_str("</tbody>\n</table>\n");
+}
+
+fn dictionary_table(html: &mut String, title: &str, dict: &Dictionary, operators: bool) {
+    let (counts, spans) = if operators {
+        (&dict.operators, &dict.operator_spans)
+    } else {
+        (&dict.operands, &dict.operand_spans)
+    };
+    let _ = writeln!(
+        html,
+        "<h3>{title}</h3>\n<table class=\"sortable\">\n\
+         <thead><tr><th>{title}</th><th>Count</th><th>First at</th></tr></thead>\n<tbody>"
+    );
+    for token in tokens(counts, spans) {
+        let first = token.occurrences.first();
+        let _ = writeln!(
+            html,
+            "<tr><td><code>{}</code></td><td class=\"number\">{}</td><td class=\"number\" data-value=\"{}\">{}</td></tr>",
+            escape(token.name),
+            token.count,
+            first.map_or(0, |span| span.start),
+            first.map_or_else(String::new, |span| format!("{}:{}", span.line, span.column))
+        );
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+/// Self-contained HTML page with a section for every file: its metrics,
+/// annotated source, function cards, Chepin's classes and sortable
+/// operator and operand tables.
+pub fn to_html<'a>(
+    title: &str,
+    files: impl IntoIterator<Item = ReportFile<'a>>,
+    theme: SyntectTheme,
+) -> String {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let themes = ThemeSet::load_defaults();
+    let theme = &themes.themes[theme.syntect_key_name()];
+    let background = theme.settings.background.unwrap_or(Color::WHITE);
+    let foreground = theme.settings.foreground.unwrap_or(Color::BLACK);
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>{STYLE}body {{ background: {}; color: {}; }}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+        css_color(background),
+        css_color(foreground),
+        title = escape(title),
+    );
+
+    for file in files {
+        let dict = file.dict;
+        let _ = writeln!(html, "<section>\n<h2>{}</h2>", escape(file.name));
+        if !dict.parse_errors.is_empty() {
+            let _ = writeln!(
+                html,
+                "<p class=\"errors\">{} syntax errors, the metrics may be off</p>\n<ul class=\"errors\">",
+                dict.parse_errors.len()
+            );
+            for err in &dict.parse_errors {
+                let _ = writeln!(html, "<li>{}</li>", escape(&err.to_string()));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str("<h3>Metrics</h3>\n");
+        metrics_table(&mut html, &dict.report);
+
+        html.push_str(
+            "<h3>Source</h3>\n<p class=\"legend\"><span class=\"operator\">operator</span>\
+             <span class=\"operand\">operand</span></p>\n<pre class=\"source\">",
+        );
+        html.push_str(&annotated_source(file.source, dict, &syntaxes, theme));
+        html.push_str("</pre>\n");
+
+        function_cards(&mut html, dict);
+        chepin_table(&mut html, dict);
+        dictionary_table(&mut html, "Operators", dict, true);
+        dictionary_table(&mut html, "Operands", dict, false);
+        html.push_str("</section>\n");
+    }
+
+    let _ = write!(html, "<script>{SCRIPT}</script>\n</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::process_js;
+
+    /// Rows of the sortable table after the `title` heading.
+    fn table_rows<'a>(html: &'a str, title: &str) -> Vec<&'a str> {
+        let table = html.split(&format!("<h3>{title}</h3>")).nth(1).unwrap();
+        let body = table.split("<tbody>").nth(1).unwrap();
+        let body = body.split("</tbody>").next().unwrap();
+        body.lines()
+            .filter(|line| line.starts_with("<tr>"))
+            .collect()
+    }
+
+    #[test]
+    fn html_report() {
+        let source = "function less(a, b) {\n  if (a < b) return '<b>';\n}";
+        let dict = process_js(source).unwrap();
+        let file = ReportFile {
+            name: "a&b.js",
+            source,
+            dict: &dict,
+        };
+        let html = to_html("Report", [file], SyntectTheme::SolarizedDark);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>a&amp;b.js</h2>"));
+        /* Solarized (dark) background */
+        assert!(html.contains("background: #002b36"));
+
+        /* Code is escaped, annotated tokens keep their text */
+        assert!(html.contains("&#39;&lt;b&gt;&#39;"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("<span class=\"operator\" title=\"operator &lt;\">&lt;</span>"));
+        assert!(html.contains("<span class=\"operand\" title=\"operand a\">a</span>"));
+
+        /* A row for every distinct operator and operand */
+        assert_eq!(table_rows(&html, "Operators").len(), dict.operators.len());
+        assert_eq!(table_rows(&html, "Operands").len(), dict.operands.len());
+        assert!(table_rows(&html, "Operands")
+            .iter()
+            .any(|row| row.starts_with("<tr><td><code>a</code></td><td class=\"number\">2</td>")));
+
+        assert!(html.contains("<div class=\"card\"><h4>less</h4>function at 1:1"));
+        assert!(html.contains("<tr><td>C, control</td><td class=\"number\">2</td><td>a, b</td>"));
+    }
+}
@@ -0,0 +1,29 @@
+//! Halstead, Djilb, McCabe and Chepin complexity metrics of JavaScript programs.
+//!
+//! ```
+//! let dict = halstead_metrics::process_js("var x = y + 1;").unwrap();
+//! assert_eq!(dict.report.halstead.unique_operators, 2);
+//! ```
+
+pub mod export;
+#[cfg(feature = "html")]
+pub mod html;
+pub mod markdown;
+pub mod metrics;
+pub mod report;
+pub mod rules;
+pub mod sarif;
+pub mod scope;
+pub mod span;
+
+pub use metrics::{
+    process_js, process_js_with, AnalysisError, AnalysisOptions, ChepinType, Dictionary,
+    Explanation, FunctionKind, FunctionMetrics, IdentProperties, IdentifierKeys, ParseError,
+    SourceKind,
+};
+pub use report::{
+    ChepinGroups, ChepinMetrics, ChepinWeights, DjilbMetrics, HalsteadMetrics, IdentSpan,
+    McCabeMetrics, MetricRow, MetricValue, MetricsReport, SpanReport,
+};
+pub use rules::{CountingRules, NodeClass, NodeRule, RulesError};
+pub use span::Span;
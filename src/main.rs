@@ -1,10 +1,11 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use eframe::egui;
+/* Kept as egui's demo has it, parts of it are unused */
+#[allow(dead_code, non_local_definitions)]
 mod syntax_highlighting;
 
-mod metrics;
-use metrics::*;
+use halstead_metrics::*;
 
 fn main() -> Result<(), eframe::Error> {
     // Log to stdout (if you run with `RUST_LOG=debug`).
@@ -22,20 +23,198 @@ fn main() -> Result<(), eframe::Error> {
     )
 }
 
+/// Order of the rows in the identifier span table.
+#[derive(Clone, Copy, PartialEq)]
+enum SpanOrder {
+    NameAsc,
+    NameDesc,
+    SpanAsc,
+    SpanDesc,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TokenKind {
+    Operator,
+    Operand,
+}
+
+/// Operator or operand picked in one of the tables, highlighted in the editor.
+struct Selection {
+    kind: TokenKind,
+    token: String,
+    /// Occurrence the editor is at.
+    current: usize,
+    /// Whether the editor should move to the current occurrence.
+    jump: bool,
+}
+
+/// Background of every occurrence of the selected token.
+const OCCURRENCE_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(90, 75, 0, 90);
+/// Background of the occurrence the editor is at.
+const CURRENT_OCCURRENCE_COLOR: egui::Color32 =
+    egui::Color32::from_rgba_premultiplied(160, 80, 0, 160);
+
 struct MyApp {
     code: String,
+    /// Code the dictionary was computed from, spans are only valid for it.
+    analyzed_code: String,
     dict: Dictionary,
+    selection: Option<Selection>,
+    options: AnalysisOptions,
+    /// Counting rules profile to load, the default one if empty.
+    rules_path: String,
+    rules_error: Option<String>,
+    /// Why the code could not be analyzed.
+    analysis_error: Option<String>,
+    /// Code picked in the error or statement list, the editor moves to it.
+    jump_to_span: Option<Span>,
+    /// Directory the exports are written to.
+    export_dir: std::path::PathBuf,
+    /// Directory shown in the folder picker while it is open.
+    browsed_dir: Option<std::path::PathBuf>,
+    /// Name of the code in the JSON and CSV exports and the HTML report.
+    source_name: String,
+    /// Outcome of the last export, what was written or why it failed.
+    export_status: Option<Result<String, String>>,
+    span_order: SpanOrder,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
         Self {
             code: "".to_string(),
+            analyzed_code: "".to_string(),
             dict: Dictionary::default(),
+            selection: None,
+            options: AnalysisOptions::default(),
+            rules_path: "".to_string(),
+            rules_error: None,
+            analysis_error: None,
+            jump_to_span: None,
+            export_dir: std::env::current_dir().unwrap_or_default(),
+            browsed_dir: None,
+            source_name: "untitled.js".to_string(),
+            export_status: None,
+            span_order: SpanOrder::SpanDesc,
         }
     }
 }
 
+impl MyApp {
+    fn is_selected(&self, kind: TokenKind, token: &str) -> bool {
+        self.selection
+            .as_ref()
+            .is_some_and(|s| s.kind == kind && s.token == token)
+    }
+
+    /// Writes the files into the export directory, tells what was written
+    /// or why it failed.
+    fn export(&self, files: Vec<(&str, String)>) -> Result<String, String> {
+        let dir = self.export_dir.as_path();
+        std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+        let mut written = Vec::new();
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).map_err(|err| format!("{}: {err}", path.display()))?;
+            written.push(name);
+        }
+        Ok(format!("Saved {} to {}", written.join(", "), dir.display()))
+    }
+
+    /// Selects the token, or unselects it if it already is.
+    fn select(&mut self, kind: TokenKind, token: String) {
+        self.selection = if self.is_selected(kind, &token) {
+            None
+        } else {
+            Some(Selection {
+                kind,
+                token,
+                current: 0,
+                jump: true,
+            })
+        };
+    }
+
+    /// Syntax errors, if they match the code in the editor.
+    fn parse_errors(&self) -> &[ParseError] {
+        if self.code != self.analyzed_code {
+            return &[];
+        }
+        &self.dict.parse_errors
+    }
+
+    /// Occurrences of the selected token, if they match the code in the editor.
+    fn selected_spans(&self) -> &[Span] {
+        let Some(selection) = &self.selection else {
+            return &[];
+        };
+        if self.code != self.analyzed_code {
+            return &[];
+        }
+        let spans = match selection.kind {
+            TokenKind::Operator => &self.dict.operator_spans,
+            TokenKind::Operand => &self.dict.operand_spans,
+        };
+        spans.get(&selection.token).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Folder picker window, browsing `browsed` until a folder is chosen, which
+/// it returns, or the picker is closed.
+fn folder_picker(
+    ctx: &egui::Context,
+    browsed: &mut Option<std::path::PathBuf>,
+) -> Option<std::path::PathBuf> {
+    let dir = browsed.clone()?;
+    let mut subdirs: Vec<_> = std::fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default();
+    subdirs.sort();
+
+    let mut chosen = None;
+    let mut open = true;
+    egui::Window::new("Export folder")
+        .open(&mut open)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let parent = dir.parent();
+                if ui
+                    .add_enabled(parent.is_some(), egui::Button::new("Up"))
+                    .clicked()
+                {
+                    *browsed = parent.map(|parent| parent.to_owned());
+                }
+                ui.label(dir.display().to_string());
+            });
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for subdir in subdirs {
+                        let name = subdir.file_name().map_or_else(
+                            || subdir.display().to_string(),
+                            |name| name.to_string_lossy().into_owned(),
+                        );
+                        if ui.selectable_label(false, format!("{name}/")).clicked() {
+                            *browsed = Some(subdir);
+                        }
+                    }
+                });
+            if ui.button("Choose this folder").clicked() {
+                chosen = Some(dir.clone());
+            }
+        });
+    if !open || chosen.is_some() {
+        *browsed = None;
+    }
+    chosen
+}
+
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.set_pixels_per_point(1.5);
@@ -59,19 +238,196 @@ impl eframe::App for MyApp {
                             });
                         })
                         .body(|mut body| {
-                            for (param, value) in &self.dict.properties {
+                            for metric in self.dict.report.rows() {
                                 body.row(30.0, |mut row| {
                                     row.col(|ui| {
-                                        ui.label(param);
+                                        ui.label(metric.label);
                                     });
                                     row.col(|ui| {
-                                        ui.label(value);
+                                        ui.label(metric.value.to_string());
                                     });
                                 });
                             }
                         });
                 });
 
+                ui.collapsing("Chepin", |ui| {
+                    let w = &mut self.options.chepin_weights;
+                    let mut changed = false;
+                    ui.horizontal(|ui| {
+                        for (name, weight) in [
+                            ("P", &mut w.p),
+                            ("M", &mut w.m),
+                            ("C", &mut w.c),
+                            ("T", &mut w.t),
+                        ] {
+                            ui.label(name);
+                            changed |= ui.add(egui::DragValue::new(weight).speed(0.1)).changed();
+                        }
+                    });
+                    if changed {
+                        self.dict
+                            .compute_properties_with(&self.options.chepin_weights);
+                    }
+
+                    let chepin = &self.dict.report.chepin;
+                    ui.push_id(4, |ui| {
+                        TableBuilder::new(ui)
+                            .striped(true)
+                            .column(Column::initial(40.0))
+                            .column(Column::remainder())
+                            .header(10.0, |mut header| {
+                                header.col(|ui| {
+                                    ui.strong("Class");
+                                });
+                                header.col(|ui| {
+                                    ui.strong("Identifiers");
+                                });
+                            })
+                            .body(|mut body| {
+                                for (class, idents) in [
+                                    ("P", &chepin.all.p),
+                                    ("M", &chepin.all.m),
+                                    ("C", &chepin.all.c),
+                                    ("T", &chepin.all.t),
+                                ] {
+                                    body.row(20.0, |mut row| {
+                                        row.col(|ui| {
+                                            ui.label(format!("{class} ({})", idents.len()));
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(idents.join(", "));
+                                        });
+                                    });
+                                }
+                            });
+                    });
+                });
+
+                ui.collapsing("Spans", |ui| {
+                    let mut spans = self.dict.report.spans.identifiers.clone();
+                    match self.span_order {
+                        SpanOrder::NameAsc => spans.sort_by(|a, b| a.name.cmp(&b.name)),
+                        SpanOrder::NameDesc => spans.sort_by(|a, b| b.name.cmp(&a.name)),
+                        SpanOrder::SpanAsc => spans.reverse(),
+                        SpanOrder::SpanDesc => {}
+                    }
+
+                    let order = &mut self.span_order;
+                    ui.push_id(5, |ui| {
+                        TableBuilder::new(ui)
+                            .striped(true)
+                            .column(Column::initial(150.0))
+                            .column(Column::remainder())
+                            .header(20.0, |mut header| {
+                                header.col(|ui| {
+                                    let selected =
+                                        matches!(*order, SpanOrder::NameAsc | SpanOrder::NameDesc);
+                                    if ui.selectable_label(selected, "Identifier").clicked() {
+                                        *order = if *order == SpanOrder::NameAsc {
+                                            SpanOrder::NameDesc
+                                        } else {
+                                            SpanOrder::NameAsc
+                                        };
+                                    }
+                                });
+                                header.col(|ui| {
+                                    let selected =
+                                        matches!(*order, SpanOrder::SpanAsc | SpanOrder::SpanDesc);
+                                    if ui.selectable_label(selected, "Span").clicked() {
+                                        *order = if *order == SpanOrder::SpanDesc {
+                                            SpanOrder::SpanAsc
+                                        } else {
+                                            SpanOrder::SpanDesc
+                                        };
+                                    }
+                                });
+                            })
+                            .body(|mut body| {
+                                for ident in &spans {
+                                    body.row(20.0, |mut row| {
+                                        row.col(|ui| {
+                                            ui.label(&ident.name);
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(ident.span.to_string());
+                                        });
+                                    });
+                                }
+                            });
+                    });
+                    ui.label(format!("Total span: {}", self.dict.report.spans.total));
+                });
+
+                ui.collapsing("Functions", |ui| {
+                    ui.push_id(3, |ui| {
+                        TableBuilder::new(ui)
+                            .striped(true)
+                            .column(Column::initial(150.0))
+                            .column(Column::initial(50.0))
+                            .columns(Column::initial(70.0), 4)
+                            .column(Column::remainder())
+                            .header(10.0, |mut header| {
+                                for title in [
+                                    "Function",
+                                    "Line",
+                                    "Volume",
+                                    "Difficulty",
+                                    "Effort",
+                                    "CC",
+                                    "Cog",
+                                ] {
+                                    header.col(|ui| {
+                                        ui.strong(title);
+                                    });
+                                }
+                            })
+                            .body(|mut body| {
+                                for function in &self.dict.functions {
+                                    let h = &function.dict.report.halstead;
+                                    body.row(20.0, |mut row| {
+                                        row.col(|ui| {
+                                            ui.label(&function.name)
+                                                .on_hover_text(function.kind.name());
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(function.span.line.to_string());
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(format!("{:.1}", h.volume));
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(format!("{:.1}", h.difficulty));
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(format!("{:.1}", h.effort));
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(
+                                                function
+                                                    .dict
+                                                    .report
+                                                    .mccabe
+                                                    .cyclomatic_complexity
+                                                    .to_string(),
+                                            );
+                                        });
+                                        row.col(|ui| {
+                                            ui.label(
+                                                function
+                                                    .dict
+                                                    .report
+                                                    .cognitive_complexity
+                                                    .to_string(),
+                                            );
+                                        });
+                                    });
+                                }
+                            });
+                    });
+                });
+
+                let mut clicked = None;
                 ui.columns(2, |columns| {
                     columns[0].push_id(1, |ui| {
                         TableBuilder::new(ui)
@@ -90,7 +446,12 @@ impl eframe::App for MyApp {
                                 for (operand, amount) in self.dict.operands.iter() {
                                     body.row(30.0, |mut row| {
                                         row.col(|ui| {
-                                            ui.label(operand);
+                                            let selected =
+                                                self.is_selected(TokenKind::Operand, operand);
+                                            if ui.selectable_label(selected, operand).clicked() {
+                                                clicked =
+                                                    Some((TokenKind::Operand, operand.clone()));
+                                            }
                                         });
                                         row.col(|ui| {
                                             ui.label(format!("{amount}"));
@@ -114,10 +475,15 @@ impl eframe::App for MyApp {
                                 });
                             })
                             .body(|mut body| {
-                                for (operand, amount) in self.dict.operators.iter() {
+                                for (operator, amount) in self.dict.operators.iter() {
                                     body.row(30.0, |mut row| {
                                         row.col(|ui| {
-                                            ui.label(operand);
+                                            let selected =
+                                                self.is_selected(TokenKind::Operator, operator);
+                                            if ui.selectable_label(selected, operator).clicked() {
+                                                clicked =
+                                                    Some((TokenKind::Operator, operator.clone()));
+                                            }
                                         });
                                         row.col(|ui| {
                                             ui.label(format!("{amount}"));
@@ -127,31 +493,165 @@ impl eframe::App for MyApp {
                             });
                     });
                 });
+                if let Some((kind, token)) = clicked {
+                    self.select(kind, token);
+                }
             });
 
+        if !self.dict.parse_errors.is_empty() {
+            egui::TopBottomPanel::bottom("parse_errors")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!(
+                            "{} syntax errors, the metrics may be off",
+                            self.dict.parse_errors.len()
+                        ),
+                    );
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for err in &self.dict.parse_errors {
+                            if ui.selectable_label(false, err.to_string()).clicked() {
+                                self.jump_to_span = Some(err.span);
+                            }
+                        }
+                    });
+                });
+        }
+
+        if let Some(dir) = folder_picker(ctx, &mut self.browsed_dir) {
+            self.export_dir = dir;
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Javascript halstead complexity");
-            if ui.button("Compute").clicked() {
-                self.dict = process_js(&self.code);
-                self.dict.compute_properties();
-
-                let mut op_csv = String::new();
-                for (op, n) in self.dict.operators.iter() {
-                    op_csv.push_str(&format!("{op}, {n}\n"));
+            ui.horizontal(|ui| {
+                ui.label(format!("Counting rules: {}", self.options.rules.name));
+                ui.text_edit_singleline(&mut self.rules_path)
+                    .on_hover_text("TOML or JSON profile, empty for the default one");
+                if ui.button("Load").clicked() {
+                    let rules = if self.rules_path.is_empty() {
+                        Ok(CountingRules::default())
+                    } else {
+                        CountingRules::load(std::path::Path::new(&self.rules_path))
+                    };
+                    match rules {
+                        Ok(rules) => {
+                            self.options.rules = rules;
+                            self.rules_error = None;
+                        }
+                        Err(err) => self.rules_error = Some(err.to_string()),
+                    }
                 }
-                std::fs::write("operators.csv", op_csv);
+            });
+            if let Some(err) = &self.rules_error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
 
-                let mut od_csv = String::new();
-                for (od, n) in self.dict.operands.iter() {
-                    od_csv.push_str(&format!("{od}, {n}\n"));
+            let compute = ui
+                .horizontal(|ui| {
+                    let mut by_name = self.options.identifier_keys == IdentifierKeys::Name;
+                    if ui
+                        .checkbox(&mut by_name, "Aggregate variables by name")
+                        .changed()
+                    {
+                        self.options.identifier_keys = if by_name {
+                            IdentifierKeys::Name
+                        } else {
+                            IdentifierKeys::Binding
+                        };
+                    }
+                    ui.checkbox(&mut self.options.explain, "Explain statements")
+                        .on_hover_text("Record why every Djilb's statement is counted");
+                    egui::ComboBox::from_label("Parse as")
+                        .selected_text(self.options.source_kind.name())
+                        .show_ui(ui, |ui| {
+                            for kind in [
+                                SourceKind::Script,
+                                SourceKind::Module,
+                                SourceKind::TypeScript,
+                            ] {
+                                ui.selectable_value(
+                                    &mut self.options.source_kind,
+                                    kind,
+                                    kind.name(),
+                                );
+                            }
+                        });
+                    ui.button("Compute").clicked()
+                })
+                .inner;
+            if let Some(err) = &self.analysis_error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+            if compute {
+                match process_js_with(&self.code, &self.options) {
+                    Ok(dict) => {
+                        self.dict = dict;
+                        self.analysis_error = None;
+                    }
+                    Err(err) => {
+                        self.dict = Dictionary::default();
+                        self.analysis_error = Some(err.to_string());
+                    }
                 }
-                std::fs::write("operands.csv", od_csv);
+                self.analyzed_code = self.code.clone();
+                self.selection = None;
+            }
 
-                let mut props = String::new();
-                for (p, v) in self.dict.properties.iter() {
-                    props.push_str(&format!("{p}, {v}\n"));
+            ui.horizontal(|ui| {
+                ui.label(format!("Export to {}", self.export_dir.display()));
+                if ui.button("Choose folder").clicked() {
+                    self.browsed_dir = Some(self.export_dir.clone());
+                }
+                ui.label("Source");
+                ui.text_edit_singleline(&mut self.source_name)
+                    .on_hover_text("Source file name in the exports and the HTML report");
+                if ui.button("Export JSON").clicked() {
+                    let json = export::to_json([(Some(self.source_name.as_str()), &self.dict)]);
+                    self.export_status = Some(self.export(vec![("analysis.json", json)]));
+                }
+                if ui.button("Export CSV").clicked() {
+                    let files = export::to_csv(&self.source_name, &self.dict);
+                    self.export_status = Some(self.export(files));
+                }
+                if ui.button("Export HTML").clicked() {
+                    let theme = syntax_highlighting::CodeTheme::from_memory(ui.ctx());
+                    let file = html::ReportFile {
+                        name: &self.source_name,
+                        source: &self.analyzed_code,
+                        dict: &self.dict,
+                    };
+                    let report = html::to_html(&self.source_name, [file], theme.syntect_theme());
+                    self.export_status = Some(self.export(vec![("report.html", report)]));
+                }
+            });
+            match &self.export_status {
+                Some(Ok(saved)) => {
+                    ui.label(saved);
+                }
+                Some(Err(err)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, err);
                 }
-                std::fs::write("properties.csv", props);
+                None => {}
+            }
+
+            if !self.dict.explanations.is_empty() {
+                ui.collapsing(
+                    format!("Statements ({})", self.dict.report.djilb.statements),
+                    |ui| {
+                        egui::ScrollArea::vertical()
+                            .max_height(200.0)
+                            .show(ui, |ui| {
+                                for explanation in &self.dict.explanations {
+                                    let label = ui.selectable_label(false, explanation.to_string());
+                                    if label.clicked() {
+                                        self.jump_to_span = Some(explanation.span);
+                                    }
+                                }
+                            });
+                    },
+                );
             }
 
             let mut theme = syntax_highlighting::CodeTheme::from_memory(ui.ctx());
@@ -162,22 +662,117 @@ impl eframe::App for MyApp {
                 });
             });
 
+            /* Occurrences of the token selected in the tables */
+            let spans = self.selected_spans().to_vec();
+            let mut jump_to = None;
+            if let Some(selection) = &mut self.selection {
+                ui.horizontal(|ui| {
+                    if spans.is_empty() {
+                        ui.label(format!(
+                            "`{}`: recompute to see occurrences",
+                            selection.token
+                        ));
+                        return;
+                    }
+                    selection.current = selection.current.min(spans.len() - 1);
+                    if ui.button("◀ Previous").clicked() {
+                        selection.current = (selection.current + spans.len() - 1) % spans.len();
+                        selection.jump = true;
+                    }
+                    if ui.button("Next ▶").clicked() {
+                        selection.current = (selection.current + 1) % spans.len();
+                        selection.jump = true;
+                    }
+                    let current = spans[selection.current];
+                    ui.label(format!(
+                        "`{}`: {} of {}, line {}, column {}",
+                        selection.token,
+                        selection.current + 1,
+                        spans.len(),
+                        current.line,
+                        current.column
+                    ));
+                    if std::mem::take(&mut selection.jump) {
+                        jump_to = Some(current);
+                    }
+                });
+            }
+            if let Some(span) = self.jump_to_span.take() {
+                jump_to = Some(span);
+            }
+            let current = self.selection.as_ref().map_or(0, |s| s.current);
+            let mut marks: Vec<_> = spans
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != current)
+                .map(|(_, span)| (span.start..span.end, OCCURRENCE_COLOR))
+                .collect();
+            if let Some(span) = spans.get(current) {
+                marks.push((span.start..span.end, CURRENT_OCCURRENCE_COLOR));
+            }
+
             let mut layouter = |ui: &egui::Ui, string: &str, wrap_width: f32| {
                 let mut layout_job = syntax_highlighting::highlight(ui.ctx(), &theme, string, "js");
+                syntax_highlighting::mark_ranges(&mut layout_job, &marks);
                 layout_job.wrap.max_width = wrap_width;
                 ui.fonts(|f| f.layout_job(layout_job))
             };
 
             egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.add(
-                    egui::TextEdit::multiline(&mut self.code)
-                        .font(egui::TextStyle::Monospace) // for cursor height
-                        .code_editor()
-                        .desired_rows(10)
-                        .lock_focus(true)
-                        .desired_width(f32::INFINITY)
-                        .layouter(&mut layouter),
-                );
+                let output = egui::TextEdit::multiline(&mut self.code)
+                    .font(egui::TextStyle::Monospace) // for cursor height
+                    .code_editor()
+                    .desired_rows(10)
+                    .lock_focus(true)
+                    .desired_width(f32::INFINITY)
+                    .layouter(&mut layouter)
+                    .show(ui);
+
+                use egui::text::{CCursor, CCursorRange};
+                let chars = |offset: usize| {
+                    CCursor::new(self.code.get(..offset).map_or(0, |s| s.chars().count()))
+                };
+
+                /* Squiggles under the first line of every syntax error */
+                let error_color = ui.visuals().error_fg_color;
+                for err in self.parse_errors() {
+                    let galley = &output.galley;
+                    let start = galley.from_ccursor(chars(err.span.start));
+                    let end = galley.from_ccursor(chars(err.span.end));
+                    let start_rect = galley.pos_from_cursor(&start);
+                    let end_x =
+                        if end.rcursor.row == start.rcursor.row && err.span.end > err.span.start {
+                            galley.pos_from_cursor(&end).min.x
+                        } else {
+                            galley.rows[start.rcursor.row.min(galley.rows.len() - 1)]
+                                .rect
+                                .max
+                                .x
+                        };
+                    let offset = output.text_draw_pos.to_vec2();
+                    syntax_highlighting::paint_squiggle(
+                        ui.painter(),
+                        start_rect.left_bottom() + offset,
+                        egui::pos2(end_x, start_rect.max.y) + offset,
+                        error_color,
+                    );
+                }
+
+                /* Select the current occurrence and scroll to it */
+                if let Some(span) = jump_to {
+                    let (start, end) = (chars(span.start), chars(span.end));
+                    let mut state = output.state;
+                    state.set_ccursor_range(Some(CCursorRange::two(start, end)));
+                    state.store(ui.ctx(), output.response.id);
+                    output.response.request_focus();
+
+                    let cursor = output.galley.from_ccursor(start);
+                    let rect = output
+                        .galley
+                        .pos_from_cursor(&cursor)
+                        .translate(output.text_draw_pos.to_vec2());
+                    ui.scroll_to_rect(rect, Some(egui::Align::Center));
+                }
             });
         });
     }
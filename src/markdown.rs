@@ -0,0 +1,313 @@
+//! Markdown summary of analyses, for pull request comments.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::export::SCHEMA_VERSION;
+use crate::metrics::{Dictionary, FunctionMetrics};
+use crate::report::{MetricValue, MetricsReport};
+
+/// Columns of the summary, by metric key.
+const COLUMNS: [(&str, &str); 6] = [
+    ("volume", "V"),
+    ("difficulty", "D"),
+    ("effort", "E"),
+    ("djilb_if_count", "Djilb CL"),
+    ("djilb_max_if_depth", "Djilb CLI"),
+    ("cyclomatic_complexity", "CC"),
+];
+
+/// Values of the metrics by their keys, `None` for floats which are not finite.
+type Metrics = HashMap<String, Option<f64>>;
+
+#[derive(Deserialize)]
+struct BaselineDocument {
+    schema_version: u32,
+    files: Vec<BaselineFile>,
+}
+
+#[derive(Deserialize)]
+struct BaselineFile {
+    path: Option<String>,
+    metrics: Metrics,
+    functions: Vec<BaselineFunction>,
+}
+
+#[derive(Deserialize)]
+struct BaselineFunction {
+    name: String,
+    metrics: Metrics,
+}
+
+/// Metrics of an earlier analysis to compare with, read from the JSON
+/// export of it.
+#[derive(Debug, Default)]
+pub struct Baseline {
+    /// Metrics of the files by their paths.
+    files: HashMap<String, Metrics>,
+    /// Metrics of the functions by the paths of their files and their
+    /// names, functions of the same name in the order they are in the file.
+    functions: HashMap<(String, String), Vec<Metrics>>,
+}
+
+#[derive(Debug)]
+pub enum BaselineError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for BaselineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Json(err) => write!(f, "invalid baseline: {err}"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "baseline has schema version {version}, expected {SCHEMA_VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BaselineError {}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self, BaselineError> {
+        let text = std::fs::read_to_string(path).map_err(BaselineError::Io)?;
+        Self::from_json(&text)
+    }
+
+    /// Reads the JSON export of an analysis, files which are not in a file
+    /// are left out.
+    pub fn from_json(text: &str) -> Result<Self, BaselineError> {
+        let document: BaselineDocument = serde_json::from_str(text).map_err(BaselineError::Json)?;
+        if document.schema_version != SCHEMA_VERSION {
+            return Err(BaselineError::UnsupportedVersion(document.schema_version));
+        }
+
+        let mut baseline = Self::default();
+        for file in document.files {
+            let Some(path) = file.path else { continue };
+            for function in file.functions {
+                baseline
+                    .functions
+                    .entry((path.clone(), function.name))
+                    .or_default()
+                    .push(function.metrics);
+            }
+            baseline.files.insert(path, file.metrics);
+        }
+        Ok(baseline)
+    }
+
+    /// Metrics of the `nth` function of the name in the file.
+    fn function(&self, path: &str, name: &str, nth: usize) -> Option<&Metrics> {
+        self.functions
+            .get(&(path.to_string(), name.to_string()))
+            .and_then(|functions| functions.get(nth))
+    }
+
+    /// Names of the functions of the file beyond the ones `seen` in it by
+    /// name, once for every one of them, sorted.
+    fn removed_functions(&self, path: &str, seen: &HashMap<&str, usize>) -> Vec<&str> {
+        let mut removed: Vec<&str> = self
+            .functions
+            .iter()
+            .filter(|((file, _), _)| file == path)
+            .flat_map(|((_, name), functions)| {
+                let kept = seen.get(name.as_str()).copied().unwrap_or_default();
+                std::iter::repeat_n(name.as_str(), functions.len().saturating_sub(kept))
+            })
+            .collect();
+        removed.sort_unstable();
+        removed
+    }
+}
+
+/// Code span of a name, pipes escaped not to end the cell.
+fn code(name: &str) -> String {
+    format!("`{}`", name.replace('|', "\\|"))
+}
+
+fn format_value(value: MetricValue) -> String {
+    match value {
+        MetricValue::Int(n) => n.to_string(),
+        MetricValue::Float(x) => format!("{x:.2}"),
+    }
+}
+
+/// Value of the metric, followed by the change since the baseline if there
+/// is any.
+fn cell(value: MetricValue, baseline: Option<&Metrics>, key: &str) -> String {
+    let formatted = format_value(value);
+    let Some(Some(old)) = baseline.and_then(|metrics| metrics.get(key)) else {
+        return formatted;
+    };
+    /* Changes lost to rounding are not worth a mention */
+    let (delta, unchanged) = match value {
+        MetricValue::Int(n) => {
+            let delta = n as f64 - old;
+            (format!("{delta:+}"), delta == 0.0)
+        }
+        MetricValue::Float(x) => {
+            let delta = f64::from(x) - old;
+            (format!("{delta:+.2}"), (delta * 100.0).round() == 0.0)
+        }
+    };
+    if unchanged {
+        formatted
+    } else {
+        format!("{formatted} ({delta})")
+    }
+}
+
+/// Row of a file or function which is not there anymore, without values.
+fn removed_row(markdown: &mut String, file: &str, function: &str) {
+    let _ = write!(markdown, "| {file} | {function} |");
+    markdown.push_str(&" - |".repeat(COLUMNS.len()));
+    markdown.push('\n');
+}
+
+fn row(
+    markdown: &mut String,
+    file: &str,
+    function: &str,
+    report: &MetricsReport,
+    baseline: Option<&Metrics>,
+) {
+    let _ = write!(markdown, "| {file} | {function} |");
+    for (key, _) in COLUMNS {
+        let value = report.get(key).expect("Summary columns are metrics");
+        let _ = write!(markdown, " {} |", cell(value, baseline, key));
+    }
+    markdown.push('\n');
+}
+
+/// Markdown table of the Halstead volume, difficulty and effort, Djilb's
+/// and cyclomatic complexity of the files, and of their functions if
+/// `functions`. With a `baseline`, changes since it follow the values in
+/// parentheses, files and functions which are not in it are marked new, and
+/// the ones of it which are gone follow as removed.
+pub fn to_markdown<'a>(
+    files: impl IntoIterator<Item = (&'a str, &'a Dictionary)>,
+    baseline: Option<&Baseline>,
+    functions: bool,
+) -> String {
+    let mut markdown = String::from("| File | Function |");
+    for (_, label) in COLUMNS {
+        let _ = write!(markdown, " {label} |");
+    }
+    markdown.push_str("\n| --- | --- |");
+    markdown.push_str(&" ---: |".repeat(COLUMNS.len()));
+    markdown.push('\n');
+
+    let new = |known: bool| {
+        if baseline.is_some() && !known {
+            " (new)"
+        } else {
+            ""
+        }
+    };
+    let mut paths = Vec::new();
+    for (path, dict) in files {
+        paths.push(path);
+        let metrics = baseline.and_then(|baseline| baseline.files.get(path));
+        let name = format!("{}{}", code(path), new(metrics.is_some()));
+        row(&mut markdown, &name, "", &dict.report, metrics);
+
+        if !functions {
+            continue;
+        }
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for FunctionMetrics { name, dict, .. } in &dict.functions {
+            let nth = seen.entry(name).or_default();
+            let metrics = baseline.and_then(|baseline| baseline.function(path, name, *nth));
+            *nth += 1;
+            let name = format!("{}{}", code(name), new(metrics.is_some()));
+            row(&mut markdown, "", &name, &dict.report, metrics);
+        }
+        for name in baseline.map_or_else(Vec::new, |b| b.removed_functions(path, &seen)) {
+            removed_row(&mut markdown, "", &format!("{} (removed)", code(name)));
+        }
+    }
+
+    let Some(baseline) = baseline else {
+        return markdown;
+    };
+    let mut removed: Vec<_> = baseline
+        .files
+        .keys()
+        .filter(|path| !paths.contains(&path.as_str()))
+        .collect();
+    removed.sort_unstable();
+    for path in removed {
+        removed_row(&mut markdown, &format!("{} (removed)", code(path)), "");
+        if functions {
+            for name in baseline.removed_functions(path, &HashMap::new()) {
+                removed_row(&mut markdown, "", &format!("{} (removed)", code(name)));
+            }
+        }
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::to_json;
+    use crate::metrics::process_js;
+
+    fn analyzed(source: &str) -> Dictionary {
+        process_js(source).unwrap()
+    }
+
+    #[test]
+    fn markdown_summary() {
+        let old = analyzed("function f(a) { if (a) { return 1; } }\nfunction h() {}");
+        let gone = analyzed("function k() {}");
+        let baseline =
+            Baseline::from_json(&to_json([(Some("a|b.js"), &old), (Some("gone.js"), &gone)]))
+                .unwrap();
+
+        let dict =
+            analyzed("function f(a) { if (a) { if (a > 1) { return 2; } } }\nfunction g() {}");
+        let markdown = to_markdown([("a|b.js", &dict)], Some(&baseline), true);
+        let lines: Vec<_> = markdown.lines().collect();
+        assert_eq!(
+            lines[0],
+            "| File | Function | V | D | E | Djilb CL | Djilb CLI | CC |"
+        );
+        assert_eq!(lines.len(), 8);
+        assert!(lines[2].starts_with("| `a\\|b.js` |  |"), "{}", lines[2]);
+        assert!(lines[3].starts_with("|  | `f` |"), "{}", lines[3]);
+        assert!(
+            lines[3].ends_with("| 2 (+1) | 2 (+1) | 3 (+1) |"),
+            "{}",
+            lines[3]
+        );
+        assert!(lines[4].starts_with("|  | `g` (new) |"), "{}", lines[4]);
+
+        /* Gone since the baseline, `h` of the file and the whole other file */
+        assert_eq!(lines[5], "|  | `h` (removed) | - | - | - | - | - | - |");
+        assert_eq!(
+            lines[6],
+            "| `gone.js` (removed) |  | - | - | - | - | - | - |"
+        );
+        assert_eq!(lines[7], "|  | `k` (removed) | - | - | - | - | - | - |");
+
+        /* Without a baseline there is nothing to compare with */
+        let markdown = to_markdown([("a|b.js", &dict)], None, false);
+        assert_eq!(markdown.lines().count(), 3);
+        assert!(!markdown.contains('('));
+
+        let newer = to_json([(Some("a.js"), &dict)])
+            .replace("\"schema_version\": 1", "\"schema_version\": 2");
+        assert!(matches!(
+            Baseline::from_json(&newer),
+            Err(BaselineError::UnsupportedVersion(2))
+        ));
+    }
+}
@@ -1,81 +1,451 @@
 use rslint_parser::SyntaxNode;
 
 extern crate rslint_parser;
-use rslint_parser::ast::Pattern;
 use rslint_parser::*;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-#[derive(Hash, Debug, Clone, Copy)]
-enum ChepinType {
-    P = 1,
-    M = 2,
-    C = 3,
-    T = 4,
+use crate::report::*;
+use crate::rules::{CountingRules, NodeClass, NodeRule};
+use crate::scope::ScopeTree;
+use crate::span::{LineIndex, Span};
+
+/// Chepin's class of a variable.
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChepinType {
+    /// Input variable, used only to compute or print something.
+    P,
+    /// Modified or created inside of the program.
+    M,
+    /// Controls the flow of the program.
+    C,
+    /// Unused.
+    T,
 }
 
+/// Variable belongs to the strongest class it was seen in, T < P < M < C.
 fn upgrade_rank(rank: ChepinType, new_rank: ChepinType) -> ChepinType {
-    if (new_rank as usize) > (rank as usize) {
+    let strength = |rank: ChepinType| match rank {
+        ChepinType::T => 0,
+        ChepinType::P => 1,
+        ChepinType::M => 2,
+        ChepinType::C => 3,
+    };
+    if strength(new_rank) > strength(rank) {
         new_rank
     } else {
         rank
     }
 }
 
-#[derive(Hash, Debug)]
+#[derive(Hash, Debug, Clone)]
 pub struct IdentProperties {
-    ctype: ChepinType,
-    spen: usize,
-    used_in: Vec<String>,
+    pub ctype: ChepinType,
+    pub spen: usize,
+    pub used_in: Vec<String>,
+    /// Passed to input/output functions.
+    pub io: bool,
+    /// Global the program does not declare, like `console`, which is not
+    /// one of its variables in Chepin's metric.
+    pub global: bool,
 }
 
+/// Functions whose arguments are program's input or output.
+const IO_FUNCTIONS: [&str; 13] = [
+    "alert",
+    "confirm",
+    "prompt",
+    "console.log",
+    "console.info",
+    "console.warn",
+    "console.error",
+    "document.write",
+    "document.writeln",
+    "process.stdout.write",
+    "fs.readFileSync",
+    "fs.writeFileSync",
+    "readline",
+];
+
 #[derive(Hash, Debug)]
 enum ScopeType {
     Block,
     ControllCondition,
-    Assignment(String),
+    /// Assignment, declaration, parameters or the head of a `for..in` or
+    /// `for..of` loop. Names on the left side get the class, anything else
+    /// inside of it is read to compute them.
+    Assignment {
+        targets: Vec<String>,
+        /// Ranges of the names assigned to.
+        names: Vec<TextRange>,
+        ctype: ChepinType,
+    },
+}
+
+/// What identifiers and operands are told apart by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdentifierKeys {
+    /// Every variable on its own, shadowed ones and locals of different
+    /// functions are keyed `name@line:column` of their declaration.
+    #[default]
+    Binding,
+    /// All variables of the same name together.
+    Name,
+}
+
+/// Language and parse goal of a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceKind {
+    /// Classic script, `import` and `export` are syntax errors.
+    #[default]
+    Script,
+    /// ECMAScript module.
+    Module,
+    /// TypeScript module.
+    TypeScript,
+}
+
+impl SourceKind {
+    /// Kind of a file by its extension, `.js` and `.cjs` are scripts, `.mjs`
+    /// modules and `.ts` TypeScript. `None` for anything else, declaration
+    /// files, `.d.ts`, included, which have no code to measure.
+    pub fn of_path(path: &std::path::Path) -> Option<Self> {
+        if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".d.ts"))
+        {
+            return None;
+        }
+        match path.extension()?.to_str()? {
+            "js" | "cjs" => Some(Self::Script),
+            "mjs" => Some(Self::Module),
+            "ts" => Some(Self::TypeScript),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Script => "script",
+            Self::Module => "module",
+            Self::TypeScript => "typescript",
+        }
+    }
+
+    /// Syntax of the kind, class fields included, which are standard since
+    /// ECMAScript 2022.
+    fn syntax(&self) -> Syntax {
+        Syntax::new(match self {
+            Self::Script => FileKind::Script,
+            Self::Module => FileKind::Module,
+            Self::TypeScript => FileKind::TypeScript,
+        })
+        .class_fields()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisOptions {
+    pub identifier_keys: IdentifierKeys,
+    /// How the program is parsed.
+    pub source_kind: SourceKind,
+    /// Record why every Djilb's statement was counted.
+    pub explain: bool,
+    /// What is counted as an operator, an operand or a statement.
+    pub rules: CountingRules,
+    /// Weights of Chepin's classes in the report.
+    pub chepin_weights: ChepinWeights,
+}
+
+/// Syntax error the parser recovered from. Metrics of a program with any
+/// are computed on the parser's guess of what was meant and may be off.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ParseError {
+    pub message: String,
+    /// Code the error is about, empty at the start of the program if the
+    /// parser did not tell.
+    pub span: Span,
+}
+
+impl ParseError {
+    fn new(diagnostic: &ParserError, lines: &LineIndex) -> Self {
+        let range = diagnostic
+            .primary
+            .as_ref()
+            .map_or(0..0, |primary| primary.span.range.clone());
+        /* Programs are shorter than 4 GiB, longer ones are not parsed */
+        let start = TextSize::from(range.start as u32);
+        let end = TextSize::from(range.end.max(range.start) as u32);
+        Self {
+            message: diagnostic.title.clone(),
+            span: lines.span(TextRange::new(start, end)),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+/// Djilb's statement, or several of them, and why it was counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub span: Span,
+    pub statements: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.reason
+        )
+    }
+}
+
+/// Why a program could not be analyzed at all. Syntax errors are not
+/// among them, the parser recovers from those into `parse_errors`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    /// Longer than the parser can address.
+    TooLarge { len: usize },
+    /// Brackets nested deeper than `MAX_NESTING`, or a syntax tree deeper
+    /// than `MAX_DEPTH`, at the 1-based line and column of the first
+    /// bracket or node too deep.
+    TooDeeplyNested { line: usize, column: usize },
+    /// The parser gave up on the program, with the message it failed with.
+    Parser(String),
+}
+
+impl std::fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { len } => write!(f, "program of {len} bytes is too large"),
+            Self::TooDeeplyNested { line, column } => {
+                write!(f, "code at {line}:{column} is nested too deeply")
+            }
+            Self::Parser(msg) => write!(f, "parser failed: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+/// Deepest nesting of brackets parsed, the parser recurses on every level.
+const MAX_NESTING: usize = 1000;
+
+/// Deepest syntax tree walked, the walker recurses on every level.
+const MAX_DEPTH: usize = 4096;
+
+/// Stack of the analysis thread, enough for programs nested as deep as the
+/// limits allow, the parser and the walker recurse on every level of
+/// nesting.
+const ANALYSIS_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Stroud number, elementary mental discriminations per second.
+const STROUD_NUMBER: f32 = 18.0;
+
+/// Volume of code expected to contain one error.
+const VOLUME_PER_BUG: f32 = 3000.0;
+
+/// `x * log2(x)`, with `0 * log2(0)` taken as 0.
+fn x_log2_x(x: usize) -> f32 {
+    if x == 0 {
+        0.0
+    } else {
+        x as f32 * (x as f32).log2()
+    }
+}
+
+/// `a / b`, with anything divided by 0 taken as 0.
+fn ratio(a: f32, b: f32) -> f32 {
+    if b == 0.0 {
+        0.0
+    } else {
+        a / b
+    }
+}
+
+/// Kind of code unit which gets its own metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FunctionKind {
+    Function,
+    Arrow,
+    Method,
+    Getter,
+    Setter,
+    Constructor,
+    Class,
+}
+
+impl FunctionKind {
+    fn of(node: &SyntaxNode) -> Option<Self> {
+        use SyntaxKind::*;
+        Some(match node.kind() {
+            FN_DECL | FN_EXPR => Self::Function,
+            ARROW_EXPR => Self::Arrow,
+            METHOD => Self::Method,
+            GETTER => Self::Getter,
+            SETTER => Self::Setter,
+            CONSTRUCTOR => Self::Constructor,
+            CLASS_DECL | CLASS_EXPR => Self::Class,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Function => "function",
+            Self::Arrow => "arrow function",
+            Self::Method => "method",
+            Self::Getter => "getter",
+            Self::Setter => "setter",
+            Self::Constructor => "constructor",
+            Self::Class => "class",
+        }
+    }
+}
+
+/// Metrics of a single function or class body.
+///
+/// Nested functions are not counted in the body of the enclosing one,
+/// they get an entry of their own.
+#[derive(Debug)]
+pub struct FunctionMetrics {
+    pub name: String,
+    pub kind: FunctionKind,
+    pub span: Span,
+    pub dict: Dictionary,
 }
 
 #[derive(Default, Debug)]
 pub struct Dictionary {
     if_depth: usize,
     switch_djilb_cli: usize,
+    /// `if` statements, whatever the counting rules call them.
+    if_count: usize,
     cur_scope: Vec<ScopeType>,
     operators_count: usize,
+    decision_points: usize,
+    cognitive_complexity: usize,
+    nesting: usize,
+    function_name: Option<String>,
+    io_call_depth: usize,
 
     pub max_if_depth: usize,
+    /// The conditional operator reaching `max_if_depth`, `None` without any.
+    pub deepest_if: Option<Span>,
     pub operators: HashMap<String, usize>,
     pub operands: HashMap<String, usize>,
+    /// Every occurrence of every operator, in source order.
+    pub operator_spans: HashMap<String, Vec<Span>>,
+    /// Every occurrence of every operand, in source order.
+    pub operand_spans: HashMap<String, Vec<Span>>,
     pub identifiers: HashMap<String, IdentProperties>,
-    pub properties: Vec<(String, String)>,
+    /// Syntax errors, in source order.
+    pub parse_errors: Vec<ParseError>,
+    /// Why every Djilb's statement was counted, in source order, only in
+    /// explain mode.
+    pub explanations: Vec<Explanation>,
+    pub report: MetricsReport,
+    /// Every function and class of the program, in source order.
+    pub functions: Vec<FunctionMetrics>,
+
+    lines: Arc<LineIndex>,
+    scopes: Arc<ScopeTree>,
+    identifier_keys: IdentifierKeys,
+    rules: Arc<CountingRules>,
+    explain: bool,
 }
 
 impl Dictionary {
-    fn add_operator(&mut self, op: String) {
+    /// Span of the whole source text.
+    pub fn source_span(&self) -> Span {
+        self.lines.whole()
+    }
+
+    /// Key of the variable a `Name` or `NameRef` refers to, `None` for
+    /// anything else, including property names and labels.
+    fn variable_key(&self, node: &SyntaxNode) -> Option<String> {
+        let binding = self.scopes.binding(node)?;
+        Some(match self.identifier_keys {
+            IdentifierKeys::Binding => binding.key.clone(),
+            IdentifierKeys::Name => binding.name.clone(),
+        })
+    }
+
+    fn add_operator(&mut self, op: String, range: TextRange) {
+        let span = self.lines.span(range);
+        self.operator_spans
+            .entry(op.clone())
+            .or_default()
+            .push(span);
         match self.operators.get(&op) {
             None => self.operators.insert(op, 1),
             Some(n) => self.operators.insert(op, n + 1),
         };
     }
 
-    fn add_operand(&mut self, od: String) {
+    fn add_operand(&mut self, od: String, range: TextRange) {
+        let span = self.lines.span(range);
+        self.operand_spans.entry(od.clone()).or_default().push(span);
         match self.operands.get(&od) {
             None => self.operands.insert(od, 1),
             Some(n) => self.operands.insert(od, n + 1),
         };
     }
 
-    fn add_identifier(&mut self, ident: String) {
-        let scope = self.cur_scope.last().expect("We are in a scope");
-        let new_ctype = match &scope {
-            ScopeType::Block => ChepinType::T,
-            ScopeType::Assignment(ident_left) => {
-                let props = self.identifiers.get_mut(ident_left).unwrap();
-                props.used_in.push(ident.clone());
-                ChepinType::M
+    /// Counts the Djilb's statements a node or token of the `kind` adds,
+    /// with the reason why in explain mode.
+    fn add_statements(&mut self, kind: SyntaxKind, statements: usize, range: TextRange) {
+        self.operators_count += statements;
+        tracing::trace!(?kind, statements, ?range, "statement");
+
+        if self.explain {
+            let plural = if statements == 1 { "" } else { "s" };
+            let mut reason = format!(
+                "{kind:?} adds {statements} statement{plural} by the `{}` rules",
+                self.rules.name
+            );
+            if kind == SyntaxKind::CALL_EXPR {
+                reason.push_str(", being a call outside of a condition");
+            }
+            self.explanations.push(Explanation {
+                span: self.lines.span(range),
+                statements,
+                reason,
+            });
+        }
+    }
+
+    fn add_identifier(&mut self, ident: String, range: TextRange, global: bool) {
+        let new_ctype = match self.cur_scope.last() {
+            Some(ScopeType::Assignment { names, ctype, .. }) if names.contains(&range) => *ctype,
+            Some(ScopeType::Assignment { targets, .. }) => {
+                for target in targets {
+                    if let Some(props) = self.identifiers.get_mut(target) {
+                        if !props.used_in.contains(&ident) {
+                            props.used_in.push(ident.clone());
+                        }
+                    }
+                }
+                ChepinType::P
             }
-            ScopeType::ControllCondition => ChepinType::C,
+            Some(ScopeType::ControllCondition) => ChepinType::C,
+            _ if self.io_call_depth > 0 => ChepinType::P,
+            _ => ChepinType::T,
         };
 
+        let is_io = self.io_call_depth > 0;
         match self.identifiers.get_mut(&ident) {
             None => {
                 self.identifiers.insert(
@@ -84,17 +454,98 @@ impl Dictionary {
                         ctype: new_ctype,
                         spen: 0,
                         used_in: Vec::new(),
+                        io: is_io,
+                        global,
                     },
                 );
             }
-            Some(IdentProperties { ctype, spen, .. }) => {
+            Some(IdentProperties {
+                ctype, spen, io, ..
+            }) => {
                 *ctype = upgrade_rank(*ctype, new_ctype);
                 *spen += 1;
+                *io |= is_io;
             }
         };
     }
 
-    pub fn compute_properties(&mut self) {
+    /// Adds all the counts of `other` to this dictionary.
+    fn merge(&mut self, other: &Dictionary) {
+        for (op, n) in &other.operators {
+            *self.operators.entry(op.clone()).or_default() += n;
+        }
+        for (od, n) in &other.operands {
+            *self.operands.entry(od.clone()).or_default() += n;
+        }
+        for (spans, other_spans) in [
+            (&mut self.operator_spans, &other.operator_spans),
+            (&mut self.operand_spans, &other.operand_spans),
+        ] {
+            for (token, other_spans) in other_spans {
+                let spans = spans.entry(token.clone()).or_default();
+                spans.extend(other_spans);
+                spans.sort_by_key(|span| span.start);
+            }
+        }
+        for (ident, other_props) in &other.identifiers {
+            match self.identifiers.get_mut(ident) {
+                None => {
+                    self.identifiers.insert(ident.clone(), other_props.clone());
+                }
+                Some(props) => {
+                    props.ctype = upgrade_rank(props.ctype, other_props.ctype);
+                    /* First occurrence in `other` is not the first one anymore */
+                    props.spen += other_props.spen + 1;
+                    for used in &other_props.used_in {
+                        if !props.used_in.contains(used) {
+                            props.used_in.push(used.clone());
+                        }
+                    }
+                    props.io |= other_props.io;
+                }
+            }
+        }
+        self.operators_count += other.operators_count;
+        self.decision_points += other.decision_points;
+        self.cognitive_complexity += other.cognitive_complexity;
+        self.switch_djilb_cli += other.switch_djilb_cli;
+        self.if_count += other.if_count;
+        if other.max_if_depth > self.max_if_depth {
+            self.max_if_depth = other.max_if_depth;
+            self.deepest_if = other.deepest_if;
+        }
+        self.explanations.extend(other.explanations.iter().cloned());
+        self.explanations.sort_by_key(|e| e.span.start);
+    }
+
+    /// Identifiers of every Chepin's class, sorted by name.
+    fn chepin_groups(&self, io_only: bool) -> ChepinGroups {
+        let mut groups = ChepinGroups::default();
+        for (ident, props) in &self.identifiers {
+            if props.global || io_only && !props.io {
+                continue;
+            }
+            let group = match props.ctype {
+                ChepinType::P => &mut groups.p,
+                ChepinType::M => &mut groups.m,
+                ChepinType::C => &mut groups.c,
+                ChepinType::T => &mut groups.t,
+            };
+            group.push(ident.clone());
+        }
+        for group in [&mut groups.p, &mut groups.m, &mut groups.c, &mut groups.t] {
+            group.sort();
+        }
+        groups
+    }
+
+    /// Computes the report again with other Chepin weights, the analysis
+    /// computes it with those of its options.
+    pub fn compute_properties_with(&mut self, weights: &ChepinWeights) {
+        for function in &mut self.functions {
+            function.dict.compute_properties_with(weights);
+        }
+
         let op_dict = self.operators.len();
         let od_dict = self.operands.len();
         let op_total: usize = self.operators.values().sum();
@@ -102,254 +553,677 @@ impl Dictionary {
 
         let program_dict = op_dict + od_dict;
         let program_len = op_total + od_total;
-        let program_volume = program_len as f32 * (od_dict as f32).log2();
-        let amount_of_ifs = self.operators.get("if ...").unwrap_or(&0) + self.switch_djilb_cli;
-
-        let mut if_saturation = amount_of_ifs as f32 / self.operators_count as f32;
-        if if_saturation.is_nan() {
-            if_saturation = 0.0;
-        }
-        let max_if_depth = self.max_if_depth;
-
-        self.properties = vec![
-            //("Unique operators".to_string(), format!("{op_dict}")),
-            //("Unique operands".to_string(), format!("{od_dict}")),
-            //("Total operators".to_string(), format!("{op_total}")),
-            //("Total operands".to_string(), format!("{od_total}")),
-            //("Program dictionary".to_string(), format!("{program_dict}")),
-            //("Program length".to_string(), format!("{program_len}")),
-            //("Program volume".to_string(), format!("{program_volume}")),
-            (
-                "Program statements: ".to_string(),
-                format!("{}", self.operators_count),
-            ),
-            (
-                "Djilb CL\n(amount of if's)".to_string(),
-                format!("{amount_of_ifs}"),
-            ),
-            (
-                "Djilb cl\n(if saturation)".to_string(),
-                format!("{if_saturation}"),
-            ),
-            (
-                "Djilb CLI\n(max if depth)".to_string(),
-                format!("{max_if_depth}"),
-            ),
-        ]
-    }
-}
-
-fn single_step(node: &SyntaxNode, ident: usize, dict: &mut Dictionary) {
-    /* => */
-    if node.is::<ast::ArrowExpr>() {
-        dict.add_operator("=>".to_string());
-    };
+        /* Empty and single token programs have a vocabulary of at most 1,
+         * their volume is 0 and so is everything derived from it. */
+        let program_volume = if program_dict > 1 {
+            program_len as f32 * (program_dict as f32).log2()
+        } else {
+            0.0
+        };
+        let estimated_len = x_log2_x(op_dict) + x_log2_x(od_dict);
+        let difficulty = ratio((op_dict * od_total) as f32, (2 * od_dict) as f32);
+        let program_level = ratio(1.0, difficulty);
+        let effort = difficulty * program_volume;
+        /* Identifiers passed to input and output calls are the parameters
+         * of the most compact implementation, a call of a built-in
+         * procedure with them, which is never larger than the program. */
+        let io_params = self.identifiers.values().filter(|props| props.io).count();
+        let potential_volume = x_log2_x(2 + io_params).min(program_volume);
 
-    /* All the `{ } blocks */
-    if node.is::<ast::BlockStmt>() {
-        dict.add_operator("{}".to_string());
-    };
+        let halstead = HalsteadMetrics {
+            unique_operators: op_dict,
+            unique_operands: od_dict,
+            total_operators: op_total,
+            total_operands: od_total,
+            vocabulary: program_dict,
+            length: program_len,
+            estimated_length: estimated_len,
+            purity_ratio: ratio(estimated_len, program_len as f32),
+            volume: program_volume,
+            potential_volume,
+            level: program_level,
+            difficulty,
+            intelligence_content: program_level * program_volume,
+            effort,
+            time: effort / STROUD_NUMBER,
+            delivered_bugs: program_volume / VOLUME_PER_BUG,
+        };
 
-    /* If statement, with or without else blocks. */
-    if node.is::<ast::IfStmt>() {
-        dict.add_operator("if ...".to_string());
-        dict.operators_count += 1;
-        eprintln!("{: <1$}{:?}", node, ident)
-    };
+        let amount_of_ifs = self.if_count + self.switch_djilb_cli;
+        let djilb = DjilbMetrics {
+            statements: self.operators_count,
+            if_count: amount_of_ifs,
+            if_saturation: ratio(amount_of_ifs as f32, self.operators_count as f32),
+            max_if_depth: self.max_if_depth,
+        };
 
-    /* Any kind of `for` loops */
-    if node.is::<ast::ForStmtInit>() {
-        dict.add_operator("for ...".to_string());
-        dict.operators_count += 2;
-        eprintln!("{: <1$}{:?}", node, ident)
-    };
+        /* Every function and the top level code are separate components of
+         * the control flow graph, each adding 1 to the complexity. Classes
+         * are not called, only their methods are. */
+        let callables = self
+            .functions
+            .iter()
+            .filter(|function| function.kind != FunctionKind::Class)
+            .count();
+        let components = 1 + callables;
+        let mccabe = McCabeMetrics {
+            decision_points: self.decision_points,
+            cyclomatic_complexity: self.decision_points + components,
+        };
 
-    /* Any kind of `for` loops */
-    if node.is::<ast::WhileStmt>() {
-        dict.add_operator("while ...".to_string());
-        dict.operators_count += 1;
-        eprintln!("{: <1$}{:?}", node, ident)
-    };
+        let all = self.chepin_groups(false);
+        let io = self.chepin_groups(true);
+        let chepin = ChepinMetrics {
+            q: all.q(weights),
+            io_q: io.q(weights),
+            all,
+            io,
+        };
 
-    /* Any kind of `for` loops */
-    if node.is::<ast::DoWhileStmt>() {
-        dict.add_operator("do ... while ...".to_string());
-        dict.operators_count += 1;
-        eprintln!("{: <1$}{:?}", node, ident)
-    };
+        let spans = SpanReport::new(
+            self.identifiers
+                .iter()
+                .map(|(name, props)| IdentSpan {
+                    name: name.clone(),
+                    span: props.spen,
+                })
+                .collect(),
+        );
 
-    /* All the `=` signs */
-    if node.is::<ast::Declarator>() {
-        dict.add_operator("=".to_string());
-        dict.operators_count += 1;
-        eprintln!("{: <1$}{:?}", node, ident)
-    };
+        self.report = MetricsReport {
+            halstead,
+            djilb,
+            mccabe,
+            cognitive_complexity: self.cognitive_complexity,
+            chepin,
+            spans,
+        };
+    }
+}
 
-    /* All the `=` signs */
-    if node.is::<ast::AssignExpr>() {
-        let expr = ast::AssignExpr::cast(node.clone()).unwrap();
-        let op_token = expr.op_token().unwrap().to_string();
-        dict.add_operator(op_token);
-        dict.operators_count += 1;
-        eprintln!("{: <1$}{:?}", node, ident)
-    };
+/// Whether the node branches the control flow, for McCabe's complexity.
+fn is_decision_point(node: &SyntaxNode) -> bool {
+    use SyntaxKind::*;
+    match node.kind() {
+        IF_STMT | FOR_STMT | FOR_IN_STMT | FOR_OF_STMT | WHILE_STMT | DO_WHILE_STMT | COND_EXPR
+        | CATCH_CLAUSE | CASE_CLAUSE => true,
+        BIN_EXPR => matches!(
+            node.to::<ast::BinExpr>().op(),
+            Some(ast::BinOp::LogicalAnd | ast::BinOp::LogicalOr | ast::BinOp::NullishCoalescing)
+        ),
+        ASSIGN_EXPR => matches!(
+            node.to::<ast::AssignExpr>().op(),
+            Some(
+                ast::AssignOp::LogicalAndAssign
+                    | ast::AssignOp::LogicalOrAssign
+                    | ast::AssignOp::NullishCoalescingAssign
+            )
+        ),
+        _ => false,
+    }
+}
 
-    /* Dots inside object.paths */
-    if node.is::<ast::DotExpr>() {
-        dict.add_operator(".".to_string());
-    };
+/// Whether the `if` statement is the `else` branch of another one.
+fn is_else_if(node: &SyntaxNode) -> bool {
+    node.parent()
+        .and_then(|parent| parent.try_to::<ast::IfStmt>())
+        .and_then(|parent| parent.alt())
+        .is_some_and(|alt| alt.syntax() == node)
+}
 
-    /* Dots inside object.paths */
-    if node.is::<ast::GroupingExpr>() {
-        dict.add_operator("( )".to_string());
-    };
+/// Adds the cognitive complexity of the node itself, as defined by SonarSource.
+///
+/// Returns whether the node nests its children one level deeper. Only the
+/// nesting inside the current function counts, nested functions are scored
+/// on their own.
+fn cognitive_step(node: &SyntaxNode, dict: &mut Dictionary) -> bool {
+    use SyntaxKind::*;
 
-    /* Constructor expression */
-    if node.is::<ast::NewExpr>() {
-        dict.add_operator("new ...".to_string());
+    let nests = match node.kind() {
+        /* `else if` breaks the flow, but is on the same level as its `if` */
+        IF_STMT if is_else_if(node) => {
+            dict.cognitive_complexity += 1;
+            false
+        }
+        IF_STMT | SWITCH_STMT | FOR_STMT | FOR_IN_STMT | FOR_OF_STMT | WHILE_STMT
+        | DO_WHILE_STMT | COND_EXPR | CATCH_CLAUSE => {
+            dict.cognitive_complexity += 1 + dict.nesting;
+            true
+        }
+        _ => false,
     };
 
-    /* Any identifiers/literals. */
-    if node.is::<ast::Name>() || node.is::<ast::NameRef>() || node.is::<ast::Literal>() {
-        let ident_or_lit = node.text().to_string();
-        dict.add_operand(ident_or_lit);
+    match node.kind() {
+        /* Plain `else` */
+        IF_STMT => {
+            let alt = node.to::<ast::IfStmt>().alt();
+            if alt.is_some_and(|alt| !alt.syntax().is::<ast::IfStmt>()) {
+                dict.cognitive_complexity += 1;
+            }
+        }
+        /* Every sequence of the same logical operator, `a && b && c || d` is 2 */
+        BIN_EXPR => {
+            let op = |node: &SyntaxNode| node.try_to::<ast::BinExpr>().and_then(|e| e.op());
+            let is_logical = matches!(
+                op(node),
+                Some(
+                    ast::BinOp::LogicalAnd | ast::BinOp::LogicalOr | ast::BinOp::NullishCoalescing
+                )
+            );
+            if is_logical && node.parent().and_then(|p| op(&p)) != op(node) {
+                dict.cognitive_complexity += 1;
+            }
+        }
+        /* Jumps to labels */
+        BREAK_STMT | CONTINUE_STMT if node.children().any(|child| child.is::<ast::NameRef>()) => {
+            dict.cognitive_complexity += 1;
+        }
+        _ => {}
+    }
+
+    nests
+}
+
+/// Keywords or punctuation standing for the whole node counted as an operator.
+fn operator_token_kinds(kind: SyntaxKind) -> &'static [SyntaxKind] {
+    use SyntaxKind::*;
+    match kind {
+        ARROW_EXPR => &[FAT_ARROW],
+        IF_STMT => &[IF_KW],
+        FOR_STMT | FOR_IN_STMT | FOR_OF_STMT => &[FOR_KW],
+        WHILE_STMT => &[WHILE_KW],
+        DO_WHILE_STMT => &[DO_KW],
+        DECLARATOR | ASSIGN_PATTERN | CLASS_PROP | PRIVATE_PROP => &[EQ],
+        DOT_EXPR => &[DOT, QUESTIONDOT],
+        NEW_EXPR => &[NEW_KW],
+        RETURN_STMT => &[RETURN_KW],
+        THROW_STMT => &[THROW_KW],
+        COND_EXPR => &[QUESTION],
+        SEQUENCE_EXPR => &[COMMA],
+        SPREAD_ELEMENT | SPREAD_PROP | REST_PATTERN => &[DOT2],
+        AWAIT_EXPR => &[AWAIT_KW],
+        YIELD_EXPR => &[YIELD_KW],
+        TRY_STMT => &[TRY_KW],
+        CATCH_CLAUSE => &[CATCH_KW],
+        FINALIZER => &[FINALLY_KW],
+        BREAK_STMT => &[BREAK_KW],
+        CONTINUE_STMT => &[CONTINUE_KW],
+        LABELLED_STMT => &[COLON],
+        SWITCH_STMT => &[SWITCH_KW],
+        CASE_CLAUSE => &[CASE_KW],
+        DEFAULT_CLAUSE => &[DEFAULT_KW],
+        CLASS_DECL | CLASS_EXPR => &[CLASS_KW],
+        GETTER => &[GET_KW],
+        SETTER => &[SET_KW],
+        SUPER_CALL => &[SUPER_KW],
+        TEMPLATE_ELEMENT => &[DOLLARCURLY],
+        IMPORT_DECL => &[IMPORT_KW],
+        EXPORT_DECL | EXPORT_DEFAULT_DECL | EXPORT_DEFAULT_EXPR | EXPORT_WILDCARD => &[EXPORT_KW],
+        _ => &[],
+    }
+}
+
+/// Tokens of a node counted as an operator. Nodes like `a, b, c` have
+/// several of them and are counted once per token.
+fn operator_tokens(node: &SyntaxNode) -> Vec<SyntaxToken> {
+    use SyntaxKind::*;
+    let op_token = match node.kind() {
+        ASSIGN_EXPR => node.to::<ast::AssignExpr>().op_token(),
+        BIN_EXPR => node.to::<ast::BinExpr>().op_token(),
+        UNARY_EXPR => node.to::<ast::UnaryExpr>().op_token(),
+        kind => {
+            let kinds = operator_token_kinds(kind);
+            let mut tokens = node
+                .children_with_tokens()
+                .filter_map(|child| child.into_token())
+                .filter(|token| kinds.contains(&token.kind()));
+            if kind == SEQUENCE_EXPR {
+                return tokens.collect();
+            }
+            tokens.next()
+        }
     };
+    op_token.into_iter().collect()
+}
+
+fn has_token(node: &SyntaxNode, kind: SyntaxKind) -> bool {
+    node.children_with_tokens()
+        .any(|child| child.as_token().is_some_and(|token| token.kind() == kind))
+}
+
+/// Node naming the function a call calls, `f` of `a.b.f()`. Calls of
+/// anything else, like `(function () {})()`, have no name.
+fn callee_name(call: &SyntaxNode) -> Option<SyntaxNode> {
+    use SyntaxKind::*;
+    let callee = call.to::<ast::CallExpr>().callee()?;
+    let callee = callee.syntax();
+    match callee.kind() {
+        NAME_REF => Some(callee.clone()),
+        DOT_EXPR | BRACKET_EXPR => callee.last_child(),
+        _ => None,
+    }
+}
+
+/// Name of a node counted as an operator, unless the rule names it.
+fn operator_name(node: &SyntaxNode, token: Option<&SyntaxToken>) -> String {
+    use SyntaxKind::*;
+    let optional = || has_token(node, QUESTIONDOT);
+    match node.kind() {
+        CALL_EXPR => {
+            let callee =
+                callee_name(node).map_or_else(String::new, |name| name.trimmed_text().to_string());
+            let call = if optional() { "?.()" } else { "()" };
+            format!("{callee}{call}")
+        }
+        BRACKET_EXPR if optional() => "?.[ ... ]".to_string(),
+        BRACKET_EXPR => "[ ... ]".to_string(),
+        /* Tagged templates are named by their tag, like calls */
+        TEMPLATE => match node.first_child().filter(|c| c.kind() != TEMPLATE_ELEMENT) {
+            Some(tag) => format!("{}` `", tag.trimmed_text()),
+            None => "` `".to_string(),
+        },
+        YIELD_EXPR if has_token(node, STAR) => "yield*".to_string(),
+        _ => token.map_or_else(|| node.trimmed_text().to_string(), |t| t.to_string()),
+    }
+}
+
+/// Name of an operand which is not a variable, and where it is.
+fn operand_name(node: &SyntaxNode) -> (String, TextRange) {
+    use SyntaxKind::*;
+    /* `#x` is another name than `x` */
+    if let Some(private) = node.parent().filter(|p| p.kind() == PRIVATE_NAME) {
+        return (private.text().to_string(), private.trimmed_range());
+    }
+    /* `enum E { A = 1 }`, named like properties are */
+    let name = (node.kind() == TS_ENUM_MEMBER)
+        .then(|| {
+            node.children_with_tokens()
+                .filter_map(|child| child.into_token())
+                .find(|token| matches!(token.kind(), IDENT | STRING))
+        })
+        .flatten();
+    match name {
+        Some(name) => (name.text().to_string(), name.text_range()),
+        None => (node.text().to_string(), node.trimmed_range()),
+    }
+}
 
-    /* Identifiers */
-    if node.is::<ast::Name>() || node.is::<ast::NameRef>() {
-        let ident = node.text().to_string();
-        dict.add_identifier(ident);
+/// Adds the node as an operator, returns the ranges of the tokens standing for it.
+fn count_operator(node: &SyntaxNode, rule: &NodeRule, dict: &mut Dictionary) -> Vec<TextRange> {
+    let tokens = operator_tokens(node);
+    /* Fields without a value, `x;`, assign nothing */
+    if tokens.is_empty()
+        && matches!(
+            node.kind(),
+            SyntaxKind::CLASS_PROP | SyntaxKind::PRIVATE_PROP
+        )
+    {
+        return Vec::new();
     }
+    let name = rule
+        .name
+        .clone()
+        .unwrap_or_else(|| operator_name(node, tokens.first()));
 
-    /* Binary expressions */
-    if node.is::<ast::BinExpr>() {
-        let bin_expr = ast::BinExpr::cast(node.clone()).unwrap();
-        let text = bin_expr.op_token().unwrap().to_string();
-        dict.add_operator(text);
+    if tokens.is_empty() {
+        let callee = node
+            .is::<ast::CallExpr>()
+            .then(|| callee_name(node))
+            .flatten();
+        let range = callee.map_or_else(|| node.trimmed_range(), |c| c.trimmed_range());
+        dict.add_operator(name, range);
+        return Vec::new();
     }
 
-    /* Unary expressions */
-    if node.is::<ast::UnaryExpr>() {
-        let un_expr = ast::UnaryExpr::cast(node.clone()).unwrap();
-        let text = un_expr.op_token().unwrap().to_string();
-        dict.add_operator(text);
+    let ranges: Vec<_> = tokens.iter().map(|token| token.text_range()).collect();
+    for &range in &ranges {
+        dict.add_operator(name.clone(), range);
     }
+    ranges
+}
 
-    /* Unary expressions */
-    if node.is::<ast::ReturnStmt>() {
-        dict.add_operator("return ...".to_string());
-        dict.operators_count += 1;
-        eprintln!("{: <1$}{:?}", node, ident)
+/// Counts the node as the counting rules say, with or without the statements it adds.
+///
+/// Tokens of the node with rules of their own, like the string parts of
+/// templates, are counted too, unless they stand for the node itself.
+fn count_node(node: &SyntaxNode, dict: &mut Dictionary, with_statements: bool) {
+    let rules = dict.rules.clone();
+
+    let mut counted = Vec::new();
+    if let Some(rule) = rules.rule(node.kind()) {
+        if with_statements && rule.statements > 0 {
+            dict.add_statements(node.kind(), rule.statements, node.trimmed_range());
+        }
+
+        match rule.class {
+            NodeClass::Operator => counted = count_operator(node, rule, dict),
+            /* Variables by their binding, literals by their text */
+            NodeClass::Operand => {
+                let (operand, range) = match dict.variable_key(node) {
+                    Some(key) => (key, node.trimmed_range()),
+                    None => operand_name(node),
+                };
+                dict.add_operand(operand, range);
+            }
+            NodeClass::Ignored | NodeClass::Skipped => {}
+        }
     }
 
-    /* Unary expressions */
-    if node.is::<ast::ThrowStmt>() {
-        dict.add_operator("return ...".to_string());
-        dict.operators_count += 1;
-        eprintln!("{: <1$}{:?}", node, ident)
+    for token in node.children_with_tokens().filter_map(|c| c.into_token()) {
+        let Some(rule) = rules.rule(token.kind()) else {
+            continue;
+        };
+        if counted.contains(&token.text_range()) {
+            continue;
+        }
+        if with_statements && rule.statements > 0 {
+            dict.add_statements(token.kind(), rule.statements, token.text_range());
+        }
+        match rule.class {
+            NodeClass::Operator => {
+                let name = rule.name.clone().unwrap_or_else(|| token.to_string());
+                dict.add_operator(name, token.text_range());
+            }
+            NodeClass::Operand => dict.add_operand(token.to_string(), token.text_range()),
+            NodeClass::Ignored | NodeClass::Skipped => {}
+        }
+    }
+}
+
+fn single_step(node: &SyntaxNode, dict: &mut Dictionary) {
+    /* Branches of the control flow: conditions, loops, cases, `catch`, `&&`, `||` and `??` */
+    if is_decision_point(node) {
+        dict.decision_points += 1;
+    }
+
+    /* Variables, but not property names or labels */
+    if let Some(ident) = dict.variable_key(node) {
+        let global = dict
+            .scopes
+            .binding(node)
+            .is_some_and(|binding| binding.declared_at.is_none());
+        dict.add_identifier(ident, node.text_range(), global);
+    }
+
+    count_node(node, dict, true);
+}
+
+/// Name of a function, or the name it is assigned to if it is anonymous.
+fn function_name(node: &SyntaxNode, kind: FunctionKind) -> String {
+    let own_name = node
+        .children()
+        .find(|child| child.is::<ast::Name>() || child.is::<ast::PropName>())
+        .map(|name| name.trimmed_text().to_string());
+
+    let name = match kind {
+        FunctionKind::Constructor => Some("constructor".to_string()),
+        FunctionKind::Getter => own_name.map(|name| format!("get {name}")),
+        FunctionKind::Setter => own_name.map(|name| format!("set {name}")),
+        _ => own_name,
+    };
+
+    name.or_else(|| {
+        /* `var name = function () {}`, `name = () => {}` or `{ name: function () {} }` */
+        let parent = node.parent()?;
+        let target = if let Some(decl) = parent.try_to::<ast::Declarator>() {
+            decl.pattern()?.syntax().clone()
+        } else if parent.is::<ast::AssignExpr>() || parent.is::<ast::LiteralProp>() {
+            parent.first_child().filter(|child| child != node)?
+        } else {
+            return None;
+        };
+        Some(target.trimmed_text().to_string())
+    })
+    .unwrap_or_else(|| "<anonymous>".to_string())
+}
+
+/// Walks a function or class into a dictionary of its own.
+fn walk_function(node: &SyntaxNode, kind: FunctionKind, dict: &mut Dictionary) {
+    let name = function_name(node, kind);
+    let _span = tracing::debug_span!("function", %name, kind = kind.name()).entered();
+    let mut fdict = Dictionary {
+        cur_scope: vec![ScopeType::Block],
+        function_name: Some(name.clone()),
+        lines: dict.lines.clone(),
+        scopes: dict.scopes.clone(),
+        identifier_keys: dict.identifier_keys,
+        rules: dict.rules.clone(),
+        explain: dict.explain,
+        ..Default::default()
+    };
+
+    single_step(node, &mut fdict);
+    for child in node.children() {
+        walker(&child, &mut fdict);
     }
+    let nested = std::mem::take(&mut fdict.functions);
+    dict.functions.push(FunctionMetrics {
+        name,
+        kind,
+        span: dict.lines.span(node.trimmed_range()),
+        dict: fdict,
+    });
+    dict.functions.extend(nested);
+}
 
-    /* Array subscription operator */
-    if node.is::<ast::BracketExpr>() {
-        dict.add_operator("[ ... ]".to_string());
+/// Variables a pattern or the left side of an assignment assigns to,
+/// including every name bound by destructuring and the objects whose
+/// properties are assigned.
+fn assignment_targets(node: &SyntaxNode, targets: &mut Vec<SyntaxNode>) {
+    use SyntaxKind::*;
+    match node.kind() {
+        NAME | NAME_REF => targets.push(node.clone()),
+        SINGLE_PATTERN | IDENT_PROP => {
+            if let Some(name) = node.children().find(|c| c.kind() == NAME) {
+                targets.push(name);
+            }
+        }
+        ARRAY_PATTERN
+        | OBJECT_PATTERN
+        | REST_PATTERN
+        | ARRAY_EXPR
+        | OBJECT_EXPR
+        | SPREAD_ELEMENT
+        | SPREAD_PROP
+        | VAR_DECL
+        | FOR_STMT_INIT
+        | PARAMETER_LIST
+        | CONSTRUCTOR_PARAMETERS => {
+            for child in node.children() {
+                assignment_targets(&child, targets);
+            }
+        }
+        /* Default values are read, not assigned to */
+        ASSIGN_PATTERN | ASSIGN_EXPR | DECLARATOR => {
+            if let Some(target) = node.first_child() {
+                assignment_targets(&target, targets);
+            }
+        }
+        /* `key: pattern`, the key is a property name */
+        KEY_VALUE_PATTERN | LITERAL_PROP => {
+            if let Some(value) = node.children().nth(1) {
+                assignment_targets(&value, targets);
+            }
+        }
+        /* `o.x = 1` and `o[i] = 1` modify `o`, the index is only read */
+        DOT_EXPR | BRACKET_EXPR | GROUPING_EXPR => {
+            if let Some(object) = node.first_child() {
+                assignment_targets(&object, targets);
+            }
+        }
+        _ => {}
     }
 }
 
-fn walker(node: &SyntaxNode, ident: usize, dict: &mut Dictionary) {
-    /* Function and method's calls*/
-    if node.is::<ast::CallExpr>() {
-        if let ScopeType::Block = dict.cur_scope.last().unwrap() {
-            dict.operators_count += 1;
-            eprintln!("{: <1$}{:?}", node, ident)
+/// Scope of the node if it assigns to variables.
+fn assignment_scope(node: &SyntaxNode, dict: &Dictionary) -> Option<ScopeType> {
+    use SyntaxKind::*;
+    let (target, ctype) = match node.kind() {
+        ASSIGN_EXPR => (node.first_child()?, ChepinType::M),
+        /* Declarations without a value do not assign anything yet */
+        DECLARATOR if node.to::<ast::Declarator>().value().is_some() => {
+            (node.first_child()?, ChepinType::M)
+        }
+        FOR_IN_STMT | FOR_OF_STMT => (
+            node.children().find(|c| c.kind() == FOR_STMT_INIT)?,
+            ChepinType::M,
+        ),
+        /* Parameters are the input of their function, `x => x` included */
+        PARAMETER_LIST | CONSTRUCTOR_PARAMETERS => (node.clone(), ChepinType::P),
+        NAME if node.parent().is_some_and(|p| p.kind() == ARROW_EXPR) => {
+            (node.clone(), ChepinType::P)
         }
+        _ => return None,
+    };
+
+    let mut names = Vec::new();
+    assignment_targets(&target, &mut names);
+    Some(ScopeType::Assignment {
+        targets: names
+            .iter()
+            .filter_map(|name| dict.variable_key(name))
+            .collect(),
+        names: names.iter().map(|name| name.text_range()).collect(),
+        ctype,
+    })
+}
+
+/// Whether a call is a statement of its own, which it is not inside of conditions.
+fn is_call_statement(dict: &Dictionary) -> bool {
+    !matches!(
+        dict.cur_scope
+            .iter()
+            .rev()
+            .find(|scope| !matches!(scope, ScopeType::Assignment { .. })),
+        Some(ScopeType::ControllCondition)
+    )
+}
+
+fn walker(node: &SyntaxNode, dict: &mut Dictionary) {
+    /* Types and anything else the counting rules leave out altogether */
+    let skipped = dict.rules.rule(node.kind()).map(|rule| rule.class) == Some(NodeClass::Skipped);
+    if skipped {
+        return;
+    }
+
+    if let Some(kind) = FunctionKind::of(node) {
+        walk_function(node, kind, dict);
+        return;
+    }
+
+    /* Function and method's calls, malformed ones are walked like any other node */
+    let call = node
+        .try_to::<ast::CallExpr>()
+        .and_then(|call| Some((call.callee()?, call.arguments()?)));
+    if let Some((callee, arguments)) = call {
+        count_node(node, dict, is_call_statement(dict));
 
         /* Trying to extract function name */
-        let call_expr = ast::CallExpr::cast(node.clone()).unwrap();
-        let callee = call_expr.callee().unwrap();
         let syntax = callee.syntax();
 
-        /* If it's anything more that just single item, we process the container it is in. */
-        let func_name = match syntax.last_child() {
-            None => syntax.to_owned(),
-            Some(func_name) => {
-                single_step(syntax, ident, dict);
+        match callee_name(node) {
+            /* If it's anything more that just single item, we process the container it is in. */
+            Some(func_name) if func_name != *syntax => {
+                single_step(syntax, dict);
                 /* Callee without last node, which was supposedly method/function */
                 for child in syntax.children() {
                     if child != func_name {
-                        walker(&child, ident + 4, dict);
+                        walker(&child, dict);
                     }
                 }
-                func_name
             }
-        };
+            Some(_) => {}
+            /* Functions called right where they are defined and other
+             * expressions are walked as a whole */
+            None => walker(syntax, dict),
+        }
 
-        /* Count function name as an operator */
-        let mut function_name = func_name.trimmed_text().to_string();
-        function_name.push_str("()");
-        dict.add_operator(function_name);
+        /* Recursive calls */
+        let callee_text = syntax.trimmed_text().to_string();
+        if let Some(name) = &dict.function_name {
+            if callee_text == *name || callee_text == format!("this.{name}") {
+                dict.cognitive_complexity += 1;
+            }
+        }
 
-        /* Process function arguments */
-        for child in call_expr.arguments().unwrap().syntax().children() {
-            walker(&child, ident + 4, dict);
+        /* Process function arguments, which are input or output of I/O functions */
+        let is_io = IO_FUNCTIONS.contains(&callee_text.as_str());
+        if is_io {
+            dict.io_call_depth += 1;
+        }
+        for child in arguments.syntax().children() {
+            walker(&child, dict);
+        }
+        if is_io {
+            dict.io_call_depth -= 1;
         }
 
         return;
     };
 
+    /* Every case but the first is one more `if`, none for an empty `switch {}` */
+    let switch_branches = node
+        .try_to::<ast::SwitchStmt>()
+        .map_or(0, |stmt| stmt.cases().count().saturating_sub(1));
+
     let mut did_enter_scope: bool = false;
     if node.is::<ast::IfStmt>() {
         dict.if_depth += 1;
-        dict.cur_scope.push(ScopeType::ControllCondition);
-        did_enter_scope = true;
+        dict.if_count += 1;
     } else if node.is::<ast::SwitchStmt>() {
-        let stmt: ast::SwitchStmt = ast::SwitchStmt::cast(node.clone()).unwrap();
-        let cases_count = stmt.cases().count();
-        dict.switch_djilb_cli += cases_count - 1;
-        dict.if_depth += cases_count - 1;
-
+        dict.switch_djilb_cli += switch_branches;
+        dict.if_depth += switch_branches;
+    } else if node.is::<ast::Condition>() || node.is::<ast::ForStmtTest>() {
+        /* Only the conditions themselves, not the bodies of `if`s and loops */
         dict.cur_scope.push(ScopeType::ControllCondition);
         did_enter_scope = true;
     } else if node.is::<ast::WhileStmt>()
         || node.is::<ast::ForStmt>()
         || node.is::<ast::DoWhileStmt>()
         || node.is::<ast::Script>()
+        || node.is::<ast::Module>()
     {
         dict.cur_scope.push(ScopeType::Block);
         did_enter_scope = true;
     }
 
-    /* if node.is::<ast::AssignExpr>() || node.is::<ast::Declarator>() {
-        let left_side = if node.is::<ast::AssignExpr>() {
-            eprintln!("ASGN => {node:?}");
-            let asgn = ast::AssignExpr::cast(node.clone()).unwrap();
-            // Will panic on complex assign expressions
-            let lhs = asgn.lhs().unwrap();
-            match lhs {
-                ast::PatternOrExpr::Expr(expr) => {
-                },
-                ast::PatternOrExpr::Pattern(ptrn) => { }
-            };
-        } else {
-            todo!()
-        };
-    }*/
+    /* Variables assigned to by the node, until the body of a loop */
+    let mut did_enter_assignment = false;
+    if let Some(scope) = assignment_scope(node, dict) {
+        dict.cur_scope.push(scope);
+        did_enter_assignment = true;
+    }
 
-    //} else if node.is::<ast::BlockStmt> || node.is::<ast::TsBoolean>{
-    //}
-    dict.max_if_depth = dict.max_if_depth.max(dict.if_depth);
+    if dict.if_depth > dict.max_if_depth {
+        dict.max_if_depth = dict.if_depth;
+        dict.deepest_if = Some(dict.lines.span(node.trimmed_range()));
+    }
+
+    let nests = cognitive_step(node, dict);
+    if nests {
+        dict.nesting += 1;
+    }
 
-    single_step(node, ident, dict);
+    single_step(node, dict);
     for child in node.children() {
-        walker(&child, ident + 4, dict);
+        if did_enter_assignment && child.is::<ast::Stmt>() {
+            dict.cur_scope.pop();
+            did_enter_assignment = false;
+        }
+        walker(&child, dict);
+    }
+
+    if did_enter_assignment {
+        dict.cur_scope.pop();
+    }
+    if nests {
+        dict.nesting -= 1;
     }
 
     if node.is::<ast::IfStmt>() {
         dict.if_depth -= 1;
     } else if node.is::<ast::SwitchStmt>() {
-        let stmt: ast::SwitchStmt = ast::SwitchStmt::cast(node.clone()).unwrap();
-        let cases_count = stmt.cases().count();
-        dict.switch_djilb_cli += cases_count - 1;
-        dict.if_depth -= cases_count - 1;
+        dict.if_depth -= switch_branches;
     }
 
     if did_enter_scope {
@@ -357,10 +1231,986 @@ fn walker(node: &SyntaxNode, ident: usize, dict: &mut Dictionary) {
     }
 }
 
-pub fn process_js(source: &str) -> Dictionary {
-    let syntax = rslint_parser::parse_text(source, 0).syntax();
-    let mut dict = Default::default();
-    walker(&dbg!(syntax), 4, &mut dict);
-    eprintln!("{:?}", dict.identifiers);
+pub fn process_js(source: &str) -> Result<Dictionary, AnalysisError> {
+    process_js_with(source, &AnalysisOptions::default())
+}
+
+/// Parses and analyzes a program. Malformed parts of it are skipped, only
+/// programs the parser cannot handle at all are an error.
+///
+/// Analysis runs on a thread of its own, the parser and the walker need
+/// more stack for deeply nested programs than callers usually have.
+pub fn process_js_with(
+    source: &str,
+    options: &AnalysisOptions,
+) -> Result<Dictionary, AnalysisError> {
+    if u32::try_from(source.len()).is_err() {
+        return Err(AnalysisError::TooLarge { len: source.len() });
+    }
+    let too_deep = |at: usize| {
+        let (line, column) = LineIndex::new(source).line_col(at);
+        AnalysisError::TooDeeplyNested { line, column }
+    };
+    if let Some(at) = too_deep_bracket(source) {
+        return Err(too_deep(at));
+    }
+
+    let caller_span = tracing::Span::current();
+    let analysis = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .name("analysis".to_string())
+            .stack_size(ANALYSIS_STACK_SIZE)
+            .spawn_scoped(scope, || {
+                caller_span.in_scope(|| {
+                    /* The parser panics on some malformed programs, which
+                     * must not take the caller down with it */
+                    let syntax = options.source_kind.syntax();
+                    let parse = std::panic::catch_unwind(|| {
+                        rslint_parser::parse_with_syntax(source, 0, syntax)
+                    })
+                    .map_err(|panic| AnalysisError::Parser(panic_message(panic)))?;
+                    if let Some(node) = too_deep_node(&parse.syntax()) {
+                        return Err(too_deep(usize::from(node.text_range().start())));
+                    }
+                    Ok(analyze(parse.syntax(), parse.errors(), source, options))
+                })
+            })
+            .expect("failed to spawn the analysis thread")
+            .join()
+    });
+    analysis.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(panic) => panic
+            .downcast_ref::<&str>()
+            .map_or_else(|| "unknown error".to_string(), |msg| msg.to_string()),
+    }
+}
+
+/// Byte offset of the first bracket nested deeper than `MAX_NESTING`.
+/// Brackets in strings, comments, regular expressions and templates are
+/// not tokens of their own, so they do not count.
+fn too_deep_bracket(source: &str) -> Option<usize> {
+    use SyntaxKind::*;
+    let (tokens, _) = rslint_parser::tokenize(source, 0);
+    let mut depth = 0usize;
+    let mut at = 0;
+    for token in tokens {
+        match token.kind {
+            L_PAREN | L_BRACK | L_CURLY | DOLLARCURLY => depth += 1,
+            R_PAREN | R_BRACK | R_CURLY => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if depth > MAX_NESTING {
+            return Some(at);
+        }
+        at += token.len;
+    }
+    None
+}
+
+/// First node of the tree deeper than `MAX_DEPTH`, found without recursion.
+fn too_deep_node(syntax: &SyntaxNode) -> Option<SyntaxNode> {
+    let mut depth = 0usize;
+    for event in syntax.preorder() {
+        match event {
+            WalkEvent::Enter(node) => {
+                depth += 1;
+                if depth > MAX_DEPTH {
+                    return Some(node);
+                }
+            }
+            WalkEvent::Leave(_) => depth -= 1,
+        }
+    }
+    None
+}
+
+/// Walks a parsed program.
+fn analyze(
+    syntax: SyntaxNode,
+    errors: &[ParserError],
+    source: &str,
+    options: &AnalysisOptions,
+) -> Dictionary {
+    let _span = tracing::debug_span!(
+        "analyze",
+        len = source.len(),
+        kind = options.source_kind.name()
+    )
+    .entered();
+    tracing::trace!(tree = ?syntax);
+
+    let lines = LineIndex::new(source);
+    let mut parse_errors: Vec<_> = errors.iter().map(|e| ParseError::new(e, &lines)).collect();
+    parse_errors.sort_by_key(|e| e.span.start);
+    let mut dict = Dictionary {
+        parse_errors,
+        scopes: Arc::new(ScopeTree::build(&syntax, &lines)),
+        lines: Arc::new(lines),
+        identifier_keys: options.identifier_keys,
+        rules: Arc::new(options.rules.clone()),
+        explain: options.explain,
+        ..Default::default()
+    };
+    walker(&syntax, &mut dict);
+
+    /* Top level code and the bodies of all functions add up to the whole file */
+    let functions = std::mem::take(&mut dict.functions);
+    for function in &functions {
+        dict.merge(&function.dict);
+    }
+    dict.functions = functions;
+    dict.compute_properties_with(&options.chepin_weights);
+    tracing::debug!(
+        operators = dict.operators.len(),
+        operands = dict.operands.len(),
+        identifiers = dict.identifiers.len(),
+        functions = dict.functions.len(),
+        parse_errors = dict.parse_errors.len(),
+        "analyzed"
+    );
     dict
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::MetricValue;
+    use crate::rules::RulesError;
+
+    fn halstead(source: &str) -> HalsteadMetrics {
+        halstead_with(source, IdentifierKeys::Binding)
+    }
+
+    fn halstead_with(source: &str, identifier_keys: IdentifierKeys) -> HalsteadMetrics {
+        let options = AnalysisOptions {
+            identifier_keys,
+            ..Default::default()
+        };
+        let dict = process_js_with(source, &options).unwrap();
+        dict.report.halstead
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        let tolerance = 1e-4 * expected.abs().max(1.0);
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn empty_program() {
+        assert_eq!(halstead(""), HalsteadMetrics::default());
+    }
+
+    #[test]
+    fn single_token() {
+        let h = halstead("x");
+        assert_eq!((h.vocabulary, h.length), (1, 1));
+        assert_eq!(h.volume, 0.0);
+        assert_eq!(h.level, 0.0);
+        assert_eq!(h.difficulty, 0.0);
+        assert_eq!(h.effort, 0.0);
+        assert_eq!(h.delivered_bugs, 0.0);
+    }
+
+    #[test]
+    fn single_expression() {
+        /* Operators: `=`, `+`. Operands: `x`, `y`, `1`. */
+        let h = halstead("x = y + 1;");
+        assert_eq!((h.unique_operators, h.unique_operands), (2, 3));
+        assert_eq!((h.total_operators, h.total_operands), (2, 3));
+        assert_close(h.volume, 5.0 * 5f32.log2());
+        assert_close(h.estimated_length, 2.0 + 3.0 * 3f32.log2());
+        assert_close(h.difficulty, 1.0);
+        assert_close(h.effort, h.volume);
+    }
+
+    #[test]
+    fn potential_volume_counts_io_parameters() {
+        /* `a` and `b` are output, V* = 4 * log2(4) while L * V stays apart */
+        let h = halstead("var a = prompt(); var b = a * 2; console.log(a, b);");
+        assert_close(h.potential_volume, 8.0);
+        assert_close(h.intelligence_content, h.level * h.volume);
+        assert!((h.potential_volume - h.intelligence_content).abs() > 1.0);
+    }
+
+    #[test]
+    fn test_js_reference() {
+        let h = halstead(include_str!("../test.js"));
+        assert_eq!((h.unique_operators, h.unique_operands), (16, 15));
+        assert_eq!((h.total_operators, h.total_operands), (31, 35));
+        assert_eq!((h.vocabulary, h.length), (31, 66));
+
+        /* V = 66 * log2(31), D = 16 / 2 * 35 / 15 */
+        assert_close(h.volume, 326.9769);
+        assert_close(h.estimated_length, 122.6034);
+        assert_close(h.purity_ratio, 1.8576);
+        assert_close(h.difficulty, 18.6667);
+        assert_close(h.level, 0.053571);
+        /* No input or output, V* = 2 * log2(2) */
+        assert_close(h.potential_volume, 2.0);
+        assert_close(h.intelligence_content, 17.5166);
+        assert_close(h.effort, 6103.569);
+        assert_close(h.time, 339.0872);
+        assert_close(h.delivered_bugs, 0.108992);
+    }
+
+    #[test]
+    fn metric_suite_relations() {
+        let dict = process_js(include_str!("../test.js")).unwrap();
+        let metric = |key| match dict.report.get(key) {
+            Some(MetricValue::Float(x)) => x,
+            Some(MetricValue::Int(n)) => n as f32,
+            None => panic!("no metric `{key}`"),
+        };
+        assert_close(
+            metric("vocabulary"),
+            metric("unique_operators") + metric("unique_operands"),
+        );
+        assert_close(
+            metric("length"),
+            metric("total_operators") + metric("total_operands"),
+        );
+        assert_close(
+            metric("purity_ratio"),
+            metric("estimated_length") / metric("length"),
+        );
+        assert_close(metric("level"), 1.0 / metric("difficulty"));
+        assert_close(
+            metric("intelligence_content"),
+            metric("level") * metric("volume"),
+        );
+        assert_close(metric("effort"), metric("difficulty") * metric("volume"));
+        assert_close(metric("time"), metric("effort") / 18.0);
+        assert_close(metric("delivered_bugs"), metric("volume") / 3000.0);
+        assert!(metric("potential_volume") <= metric("volume"));
+    }
+
+    #[test]
+    fn program_js_reference() {
+        let dict = process_js(include_str!("../program.js")).unwrap();
+        assert!(dict.parse_errors.is_empty(), "{:?}", dict.parse_errors);
+
+        /* Every occurrence of these is found by a plain text search of the
+         * file, there are no comments or strings with them in it. */
+        for (operator, count) in [
+            ("if ...", 18),
+            ("for ...", 7),
+            ("return ...", 20),
+            ("throw ...", 1),
+            ("new ...", 3),
+            ("? :", 6),
+            ("===", 15),
+            ("!==", 1),
+            ("||", 2),
+            ("++", 7),
+            ("<", 7),
+            ("<=", 2),
+            (">=", 1),
+        ] {
+            assert_eq!(dict.operators.get(operator), Some(&count), "{operator}");
+        }
+        /* 18 `if`s, 7 `for`s, 6 `?:` and 2 `||` */
+        assert_eq!(dict.report.mccabe.decision_points, 33);
+
+        let h = dict.report.halstead;
+        let (n, len) = (h.vocabulary as f32, h.length as f32);
+        assert_close(h.volume, len * n.log2());
+        assert_close(
+            h.difficulty,
+            h.unique_operators as f32 / 2.0 * h.total_operands as f32 / h.unique_operands as f32,
+        );
+    }
+
+    #[test]
+    fn functions_are_reported_separately() {
+        let dict = process_js(
+            "function outer(a) {\n    var inner = (x) => x + a;\n    return inner(1);\n}\n\
+             var o = { f: function () { return 2; } };",
+        )
+        .unwrap();
+        let names: Vec<_> = dict.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["outer", "inner", "f"]);
+
+        let inner = &dict.functions[1];
+        assert_eq!(inner.kind, FunctionKind::Arrow);
+        assert_eq!((inner.span.line, inner.span.column), (2, 17));
+        assert_eq!(inner.dict.operators.get("+"), Some(&1));
+
+        /* Nested arrow function is not a part of `outer` */
+        let outer = &dict.functions[0];
+        assert_eq!(outer.dict.operators.get("+"), None);
+        assert_eq!(outer.dict.operators.get("inner()"), Some(&1));
+
+        /* But it is a part of the whole file */
+        assert_eq!(dict.operators.get("+"), Some(&1));
+        assert_eq!(dict.operators.get("return ..."), Some(&2));
+    }
+
+    #[test]
+    fn immediately_invoked_functions() {
+        let dict =
+            process_js("(function () { return 1; })();\n(() => { if (a) b(); })();\n(a || b)();")
+                .unwrap();
+        let kinds: Vec<_> = dict.functions.iter().map(|f| f.kind).collect();
+        assert_eq!(kinds, [FunctionKind::Function, FunctionKind::Arrow]);
+        assert_eq!(dict.functions[1].span.line, 2);
+        assert_eq!(dict.functions[1].dict.operators.get("if ..."), Some(&1));
+
+        assert_eq!(dict.operators.get("()"), Some(&3));
+        assert_eq!(dict.operators.get("||"), Some(&1));
+        assert_eq!(dict.operators.get("return ..."), Some(&1));
+        assert_eq!(dict.operators.get("b()"), Some(&1));
+    }
+
+    #[test]
+    fn cyclomatic_complexity() {
+        let dict = process_js(
+            "function f(a, b) {\n\
+                 for (var i = 0; i < a; i++) {\n\
+                     if (a && b || i) { continue; }\n\
+                 }\n\
+                 switch (a) { case 1: break; case 2: break; default: break; }\n\
+                 try { g(); } catch (e) { return b ? 1 : a ?? 2; }\n\
+             }\n\
+             var h = () => a || b;",
+        )
+        .unwrap();
+        let f = &dict.functions[0].dict.report.mccabe;
+        /* for, if, &&, ||, 2 cases, catch, ?:, ?? */
+        assert_eq!(f.decision_points, 9);
+        assert_eq!(f.cyclomatic_complexity, 10);
+
+        let h = &dict.functions[1].dict.report.mccabe;
+        assert_eq!(h.cyclomatic_complexity, 2);
+
+        /* Top level code, `f` and `h` */
+        assert_eq!(dict.report.mccabe.decision_points, 10);
+        assert_eq!(dict.report.mccabe.cyclomatic_complexity, 13);
+
+        /* Top level code and the method, the class itself is not called */
+        let dict = process_js("class A { m() { return 1; } }").unwrap();
+        assert_eq!(dict.report.mccabe.cyclomatic_complexity, 2);
+    }
+
+    #[test]
+    fn cognitive_complexity() {
+        let dict = process_js(
+            "function sum(items, limit) {\n\
+                 var total = 0;\n\
+                 outer: for (var i = 0; i < items.length; i++) {\n\
+                     if (items[i] > limit && limit > 0 || !limit) {\n\
+                         continue outer;\n\
+                     } else if (items[i] < 0) {\n\
+                         total += items[i] ? 1 : 0;\n\
+                     } else {\n\
+                         total += sum(items[i], limit);\n\
+                     }\n\
+                 }\n\
+                 return total;\n\
+             }",
+        )
+        .unwrap();
+        /* for +1, if +2, &&/|| sequences +2, labelled continue +1,
+         * else if +1, ?: +3, else +1, recursion +1 */
+        assert_eq!(dict.functions[0].dict.report.cognitive_complexity, 12);
+    }
+
+    #[test]
+    fn chepin_metric() {
+        let options = AnalysisOptions {
+            chepin_weights: ChepinWeights {
+                p: 1.0,
+                m: 2.0,
+                c: 3.0,
+                t: 2.0,
+            },
+            ..Default::default()
+        };
+        let source = "var a = 1, b, unused;\n\
+                      if (a > 0) { console.log(b); }";
+        let mut dict = process_js_with(source, &options).unwrap();
+        assert_eq!(dict.report.chepin.q, 1.0 + 3.0 + 2.0);
+
+        dict.compute_properties_with(&ChepinWeights::default());
+        let chepin = &dict.report.chepin;
+        assert_eq!(chepin.all.p, ["b"]);
+        assert_eq!(chepin.all.c, ["a"]);
+        /* `console` is not a variable of the program */
+        assert_eq!(chepin.all.t, ["unused"]);
+        assert_eq!(chepin.q, 1.0 + 3.0 + 0.5);
+        assert_eq!(chepin.io.p, ["b"]);
+        assert!(chepin.io.c.is_empty());
+        assert_eq!(chepin.io_q, 1.0);
+    }
+
+    #[test]
+    fn destructuring_assignment_targets() {
+        let dict = process_js(
+            "const { a, b: [c = d], ...e } = src;\n\
+             [a, f] = [f, a];\n\
+             o.x = c + g;\n\
+             for (const [k, v] of pairs) { console.log(k); }\n\
+             function h({ p = q }, [r], ...s) { return p; }\n\
+             var t = u => u;",
+        )
+        .unwrap();
+        let ctype = |ident: &str| dict.identifiers[ident].ctype;
+        for ident in ["a", "c", "e", "f", "o", "v", "t"] {
+            assert_eq!(ctype(ident), ChepinType::M, "{ident}");
+        }
+        for ident in ["d", "src", "g", "pairs", "p", "q", "r", "s", "u"] {
+            assert_eq!(ctype(ident), ChepinType::P, "{ident}");
+        }
+        /* Every target of a pattern is computed from the whole right side */
+        assert_eq!(dict.identifiers["a"].used_in, ["d", "src", "f", "a"]);
+        assert_eq!(dict.identifiers["c"].used_in, ["d", "src"]);
+        assert_eq!(dict.identifiers["f"].used_in, ["f", "a"]);
+        assert_eq!(dict.identifiers["o"].used_in, ["c", "g"]);
+        assert_eq!(dict.identifiers["k"].used_in, ["pairs"]);
+        assert_eq!(dict.identifiers["p"].used_in, ["q"]);
+    }
+
+    #[test]
+    fn identifiers_are_keyed_by_binding() {
+        let source = "var i = 0, o = { i: 1 };\n\
+                      function f(i) { return i + o.i; }\n\
+                      function g() { for (let i = 0; i < 2; i++) { var x = i; } return i + x; }";
+        let dict = process_js(source).unwrap();
+        let mut idents: Vec<_> = dict.identifiers.keys().map(String::as_str).collect();
+        idents.sort();
+        assert_eq!(idents, ["f", "g", "i@1:5", "i@2:12", "i@3:25", "o", "x"]);
+        /* Parameter `i` and its use, the property `o.i` is not a variable */
+        assert_eq!(dict.operands.get("i@2:12"), Some(&2));
+        /* `var x` is hoisted out of the loop, `let i` is not */
+        assert_eq!(dict.identifiers["x"].spen, 1);
+        assert_eq!(dict.identifiers["i@1:5"].spen, 1);
+
+        let dict = process_js_with(
+            source,
+            &AnalysisOptions {
+                identifier_keys: IdentifierKeys::Name,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(dict.identifiers["i"].spen, 7);
+    }
+
+    #[test]
+    fn occurrence_spans() {
+        let dict = process_js("var a = b + 1;\nif (a) { f(a); }").unwrap();
+        let at = |spans: &[Span]| -> Vec<_> { spans.iter().map(|s| (s.line, s.column)).collect() };
+        assert_eq!(at(&dict.operand_spans["a"]), [(1, 5), (2, 5), (2, 12)]);
+        assert_eq!(at(&dict.operator_spans["+"]), [(1, 11)]);
+        assert_eq!(at(&dict.operator_spans["if ..."]), [(2, 1)]);
+        assert_eq!(at(&dict.operator_spans["f()"]), [(2, 10)]);
+
+        let plus = dict.operator_spans["+"][0];
+        assert_eq!((plus.start, plus.end), (10, 11));
+        for (op, n) in &dict.operators {
+            assert_eq!(dict.operator_spans[op].len(), *n, "{op}");
+        }
+    }
+
+    #[test]
+    fn counting_rules_profile() {
+        let rules = CountingRules::from_toml(
+            "name = \"test\"\n\
+             [nodes.IF_STMT]\n\
+             class = \"operator\"\n\
+             name = \"if\"\n\
+             statements = 3\n\
+             [nodes.BIN_EXPR]\n\
+             class = \"operator\"\n\
+             [nodes.NAME_REF]\n\
+             class = \"operand\"\n\
+             [nodes.LITERAL]\n\
+             class = \"ignored\"",
+        )
+        .unwrap();
+        let options = AnalysisOptions {
+            rules,
+            ..Default::default()
+        };
+        let dict = process_js_with("if (a > 1) { b; }", &options).unwrap();
+
+        let mut operators: Vec<_> = dict.operators.keys().map(String::as_str).collect();
+        operators.sort();
+        assert_eq!(operators, [">", "if"]);
+        assert_eq!(dict.operands.len(), 2);
+        assert_eq!(dict.report.djilb.statements, 3);
+        assert_eq!(dict.report.djilb.if_count, 1);
+
+        /* Profiles round trip through JSON */
+        let json = options.rules.to_json();
+        assert_eq!(CountingRules::from_json(&json).unwrap(), options.rules);
+
+        assert!(matches!(
+            CountingRules::from_json(r#"{"name": "x", "nodes": {"IF": {"class": "operator"}}}"#),
+            Err(RulesError::UnknownNodeKind(kind)) if kind == "IF"
+        ));
+    }
+
+    fn module(source: &str) -> Dictionary {
+        let options = AnalysisOptions {
+            source_kind: SourceKind::Module,
+            ..Default::default()
+        };
+        process_js_with(source, &options).unwrap()
+    }
+
+    fn assert_operators(source: &str, expected: &[(&str, usize)]) {
+        let dict = module(source);
+        assert!(dict.parse_errors.is_empty(), "{:?}", dict.parse_errors);
+        for &(op, n) in expected {
+            assert_eq!(
+                dict.operators.get(op),
+                Some(&n),
+                "{op} in {:?}",
+                dict.operators
+            );
+        }
+    }
+
+    #[test]
+    fn conditional_operator() {
+        assert_operators("a ? b : c ? d : e;", &[("? :", 2)]);
+    }
+
+    #[test]
+    fn template_literals() {
+        assert_operators("`x${y}z${w}`;", &[("` `", 1), ("${ }", 2)]);
+        let dict = module("`x${y}z`;");
+        for operand in ["x", "y", "z"] {
+            assert_eq!(dict.operands.get(operand), Some(&1), "{operand}");
+        }
+    }
+
+    #[test]
+    fn tagged_templates() {
+        assert_operators("tag`q${w}`; a.b`c`;", &[("tag` `", 1), ("a.b` `", 1)]);
+    }
+
+    #[test]
+    fn spread_and_rest() {
+        assert_operators(
+            "f(...xs); var o = { ...p }; var [a, ...r] = xs; function g(...args) {}",
+            &[("...", 4)],
+        );
+    }
+
+    #[test]
+    fn optional_chaining() {
+        assert_operators(
+            "a?.b.c; a?.[1]; a?.(); a.f?.();",
+            &[
+                ("?.", 1),
+                (".", 2),
+                ("?.[ ... ]", 1),
+                ("a?.()", 1),
+                ("f?.()", 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn await_expressions() {
+        assert_operators(
+            "async function f() { await p; await q(); }",
+            &[("await", 2)],
+        );
+    }
+
+    #[test]
+    fn yield_expressions() {
+        assert_operators(
+            "function* g() { yield 1; yield* o; }",
+            &[("yield", 1), ("yield*", 1)],
+        );
+    }
+
+    #[test]
+    fn typeof_delete_void() {
+        assert_operators(
+            "typeof a; delete a.b; void 0;",
+            &[("typeof", 1), ("delete", 1), ("void", 1)],
+        );
+    }
+
+    #[test]
+    fn in_and_instanceof() {
+        assert_operators("a in b; a instanceof B;", &[("in", 1), ("instanceof", 1)]);
+    }
+
+    #[test]
+    fn try_catch_finally() {
+        assert_operators(
+            "try { f(); } catch (e) { g(e); } finally { h(); }\n\
+             try { f(); } catch { throw 1; }",
+            &[
+                ("try ...", 2),
+                ("catch ...", 2),
+                ("finally ...", 1),
+                ("throw ...", 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn break_continue_and_labels() {
+        let source = "l: for (;;) { while (x) { break l; } continue; }";
+        assert_operators(
+            source,
+            &[
+                ("label: ...", 1),
+                ("break", 1),
+                ("continue", 1),
+                ("for ...", 1),
+            ],
+        );
+        /* Label and the jump to it, which are not variables */
+        let dict = module(source);
+        assert_eq!(dict.operands.get("l"), Some(&2));
+        assert!(!dict.identifiers.contains_key("l"));
+    }
+
+    #[test]
+    fn for_in_and_for_of() {
+        assert_operators(
+            "for (k in o) {} for (const v of xs) {}",
+            &[("for ... in ...", 1), ("for ... of ...", 1)],
+        );
+    }
+
+    #[test]
+    fn switch_case() {
+        assert_operators(
+            "switch (a) { case 1: case 2: break; default: f(); }",
+            &[
+                ("switch ...", 1),
+                ("case ...", 2),
+                ("default ...", 1),
+                ("break", 1),
+            ],
+        );
+
+        /* Every case but the first is one more `if` */
+        let dict = process_js("switch (a) { case 1: f(); case 2: g(); default: h(); }").unwrap();
+        assert_eq!(dict.report.djilb.if_count, 2);
+        assert_eq!(dict.max_if_depth, 2);
+    }
+
+    #[test]
+    fn classes() {
+        let source = "class A extends B { constructor() { super(1); super.m(); } }\n\
+                      var C = class extends A {};";
+        assert_operators(
+            source,
+            &[
+                ("class ...", 2),
+                ("extends", 2),
+                ("super()", 1),
+                ("m()", 1),
+                (".", 1),
+            ],
+        );
+        assert_eq!(module(source).operands.get("super"), Some(&1));
+    }
+
+    #[test]
+    fn class_fields() {
+        /* Fields without a value assign nothing */
+        let source = "class A { x = 1; static y = this.x + 2; #p = 3; p() {} z; }";
+        assert_operators(source, &[("=", 3), ("+", 1), (".", 1)]);
+        let dict = module(source);
+        assert_eq!(dict.operands.get("this"), Some(&1));
+        assert_eq!(dict.operands.get("#p"), Some(&1));
+        assert_eq!(dict.operands.get("p"), Some(&1));
+    }
+
+    #[test]
+    fn destructuring_defaults() {
+        /* One declaration, one assignment and five default values */
+        assert_operators(
+            "const { a = 1, b: c = 2 } = o; [d = 3] = e; function f(g = 4, { h } = {}) {}",
+            &[("=", 7)],
+        );
+    }
+
+    #[test]
+    fn getters_and_setters() {
+        assert_operators(
+            "var o = { get x() { return 1; }, set x(v) {} };\n\
+             class A { get y() { return 2; } set y(v) {} }",
+            &[("get ...", 2), ("set ...", 2)],
+        );
+    }
+
+    #[test]
+    fn imports_and_exports() {
+        assert_operators(
+            "import d, { k } from \"m\"; import * as ns from \"n\";\n\
+             export const c = 1; export { c as e }; export * from \"z\";\n\
+             export default d;",
+            &[
+                ("import ...", 2),
+                ("export ...", 2),
+                ("export * ...", 1),
+                ("export default ...", 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn source_kinds() {
+        use std::path::Path;
+        let kinds: Vec<_> = ["a.js", "a.cjs", "a.mjs", "a.ts", "a.d.ts", "a.txt"]
+            .into_iter()
+            .map(|path| SourceKind::of_path(Path::new(path)))
+            .collect();
+        use SourceKind::*;
+        assert_eq!(
+            kinds,
+            [
+                Some(Script),
+                Some(Script),
+                Some(Module),
+                Some(TypeScript),
+                None,
+                None
+            ]
+        );
+
+        let source = "import a from 'a';\nexport default a;";
+        assert!(!process_js(source).unwrap().parse_errors.is_empty());
+        assert!(module(source).parse_errors.is_empty());
+
+        let source = "class A { x = 1; static y = this.x; }";
+        assert!(process_js(source).unwrap().parse_errors.is_empty());
+        assert!(module(source).parse_errors.is_empty());
+    }
+
+    #[test]
+    fn typescript() {
+        let source = "interface P<T> { x: number; f(a: T): void }\n\
+                      type U = string | P<number>;\n\
+                      enum E { A = 1, B }\n\
+                      function id<T extends object>(a: T, b?: (c: T) => U[]): T {\n\
+                          return a as T;\n\
+                      }\n\
+                      let m: Map<string, E> = new Map<string, E>();\n\
+                      m!.set(E.A, id(m));";
+        let options = AnalysisOptions {
+            source_kind: SourceKind::TypeScript,
+            ..Default::default()
+        };
+        let dict = process_js_with(source, &options).unwrap();
+        assert!(dict.parse_errors.is_empty(), "{:?}", dict.parse_errors);
+
+        /* Enums run, types do not */
+        let mut operands: Vec<_> = dict.operands.keys().map(String::as_str).collect();
+        operands.sort();
+        assert_eq!(operands, ["1", "A", "B", "E", "Map", "a", "b", "id", "m"]);
+        assert_eq!(dict.operators.get("enum ..."), Some(&1));
+        assert_eq!(dict.operands.get("a"), Some(&2));
+        assert!(!dict.identifiers.contains_key("c"));
+    }
+
+    #[test]
+    fn comma_expressions() {
+        assert_operators("a, b, c; for (i = 0, j = 1; ; i++, j++) {}", &[(",", 4)]);
+    }
+
+    #[test]
+    fn identifier_spans() {
+        let dict = process_js("var a = b; a = a + b; c = a;").unwrap();
+        let spans = &dict.report.spans;
+        let top: Vec<_> = spans
+            .top(2)
+            .iter()
+            .map(|ident| (ident.name.as_str(), ident.span))
+            .collect();
+        assert_eq!(top, [("a", 3), ("b", 1)]);
+        assert_eq!(spans.total, 4);
+        assert_eq!(spans.max(), 3);
+    }
+
+    #[test]
+    fn malformed_programs() {
+        /* No cases, no branches */
+        let dict = process_js("switch (a) {}").unwrap();
+        assert_eq!(dict.max_if_depth, 0);
+        assert_eq!(dict.report.djilb.if_count, 0);
+
+        /* Unfinished calls and assignments are still counted */
+        let dict = process_js("f(; a = ; new (").unwrap();
+        assert!(!dict.parse_errors.is_empty());
+        assert!(dict
+            .parse_errors
+            .windows(2)
+            .all(|e| e[0].span.start <= e[1].span.start));
+        assert_eq!(dict.operators.get("f()"), Some(&1));
+
+        let deep = format!("x = {}1;", "(".repeat(MAX_NESTING + 1));
+        assert_eq!(
+            process_js(&deep).err(),
+            Some(AnalysisError::TooDeeplyNested {
+                line: 1,
+                column: MAX_NESTING + 5
+            })
+        );
+        /* Brackets in strings, comments and regular expressions are not nested */
+        let brackets = "(".repeat(MAX_NESTING + 1);
+        let quoted = format!("x = '{brackets}'; /* {brackets} */ y = /\\{brackets}/;");
+        assert!(process_js(&quoted).is_ok());
+
+        /* Callers need no more than the default stack of a thread */
+        std::thread::spawn(|| {
+            assert!(process_js(&"(".repeat(MAX_NESTING)).is_ok());
+            assert!(process_js(&format!("x = {}1;", "(".repeat(MAX_NESTING))).is_ok());
+
+            /* Nesting without brackets is limited by the depth of the tree */
+            let chain = format!("if (a) x;{}", " else if (a) x;".repeat(MAX_DEPTH));
+            assert!(matches!(
+                process_js(&chain),
+                Err(AnalysisError::TooDeeplyNested { line: 1, .. })
+            ));
+            let chain = format!("if (a) x;{}", " else if (a) x;".repeat(256));
+            assert!(process_js(&chain).is_ok());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn explain_mode() {
+        let source = "var a = f();\nif (g()) {\n  function h() { return a; }\n}";
+        let options = AnalysisOptions {
+            explain: true,
+            ..Default::default()
+        };
+        let dict = process_js_with(source, &options).unwrap();
+        let explanations: Vec<_> = dict.explanations.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            explanations,
+            [
+                "1:5: DECLARATOR adds 1 statement by the `default` rules",
+                "1:9: CALL_EXPR adds 1 statement by the `default` rules, \
+                 being a call outside of a condition",
+                "2:1: IF_STMT adds 1 statement by the `default` rules",
+                "3:18: RETURN_STMT adds 1 statement by the `default` rules",
+            ]
+        );
+        let statements: usize = dict.explanations.iter().map(|e| e.statements).sum();
+        assert_eq!(statements, dict.report.djilb.statements);
+
+        assert!(process_js(source).unwrap().explanations.is_empty());
+    }
+
+    #[test]
+    fn parse_errors_have_locations() {
+        let dict = process_js("var a = 1;\nif (a { b(); }\nc = ;").unwrap();
+        let errors: Vec<_> = dict.parse_errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                "2:7: expected `')'` but instead found `{`",
+                "3:5: Expected an expression, but found none"
+            ]
+        );
+        assert_eq!(
+            dict.parse_errors[0].span.start..dict.parse_errors[0].span.end,
+            17..18
+        );
+        assert!(process_js("var a = 1;").unwrap().parse_errors.is_empty());
+    }
+
+    /// xorshift64*, random enough to mangle programs with.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as usize % n.max(1)
+        }
+    }
+
+    #[test]
+    fn random_input_never_panics() {
+        const FRAGMENTS: [&str; 32] = [
+            "(",
+            ")",
+            "{",
+            "}",
+            "[",
+            "]",
+            "switch (a) {",
+            "case 1:",
+            "default:",
+            "=>",
+            "...",
+            "`${",
+            "}`",
+            "?.",
+            "=",
+            ",",
+            ";",
+            "function",
+            "class",
+            "extends",
+            "if (",
+            "else",
+            "for (",
+            " of ",
+            "new",
+            "yield",
+            "async",
+            "await",
+            "super",
+            "'",
+            "/*",
+            "\u{1F600}",
+        ];
+        let corpus = [
+            include_str!("../test.js"),
+            "const { a, b: [c = d], ...e } = f; for (const [k, v] of m) g?.(k, ...v);",
+            "class A extends B { get x() { return super.x; } static *y() { yield* z; } }",
+        ];
+
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let options = AnalysisOptions::default();
+        for i in 0..1000 {
+            let mut chars: Vec<char> = corpus[i % corpus.len()].chars().collect();
+            for _ in 0..=rng.below(8) {
+                let at = rng.below(chars.len() + 1);
+                match rng.below(3) {
+                    0 => {
+                        let end = chars.len().min(at + rng.below(40));
+                        chars.drain(at..end);
+                    }
+                    1 => {
+                        let fragment = FRAGMENTS[rng.below(FRAGMENTS.len())];
+                        chars.splice(at..at, fragment.chars());
+                    }
+                    _ => chars.insert(at, char::from(b' ' + rng.below(95) as u8)),
+                }
+            }
+
+            let source: String = chars.into_iter().collect();
+            /* The parser has bugs of its own, it only must not take the analysis down */
+            match process_js_with(&source, &options) {
+                Ok(_) | Err(AnalysisError::Parser(_)) => {}
+                Err(err) => panic!("{err} in {source:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Halstead's software science metrics of a program.
+///
+/// Operator and operand counts are taken straight from the dictionary,
+/// everything else is derived from them.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct HalsteadMetrics {
+    /// n1, number of unique operators.
+    pub unique_operators: usize,
+    /// n2, number of unique operands.
+    pub unique_operands: usize,
+    /// N1, total amount of operators.
+    pub total_operators: usize,
+    /// N2, total amount of operands.
+    pub total_operands: usize,
+    /// n = n1 + n2
+    pub vocabulary: usize,
+    /// N = N1 + N2
+    pub length: usize,
+    /// N^ = n1 * log2(n1) + n2 * log2(n2)
+    pub estimated_length: f32,
+    /// N^ / N
+    pub purity_ratio: f32,
+    /// V = N * log2(n)
+    pub volume: f32,
+    /// V* = (2 + n2*) * log2(2 + n2*), the volume of the most compact
+    /// implementation, where n2* is the number of distinct input and output
+    /// parameters.
+    pub potential_volume: f32,
+    /// L = 1 / D
+    pub level: f32,
+    /// D = (n1 / 2) * (N2 / n2)
+    pub difficulty: f32,
+    /// I = L * V
+    pub intelligence_content: f32,
+    /// E = D * V
+    pub effort: f32,
+    /// T = E / 18, in seconds.
+    pub time: f32,
+    /// B = V / 3000
+    pub delivered_bugs: f32,
+}
+
+/// Djilb's control flow complexity metrics of a program.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct DjilbMetrics {
+    /// Amount of statements in the program.
+    pub statements: usize,
+    /// CL, amount of conditional operators, `switch` cases included.
+    pub if_count: usize,
+    /// cl = CL / statements
+    pub if_saturation: f32,
+    /// CLI, maximum nesting depth of conditional operators.
+    pub max_if_depth: usize,
+}
+
+/// McCabe's cyclomatic complexity of a program.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct McCabeMetrics {
+    /// Conditions, loops, `case` clauses, `catch` clauses, `?:`, `&&`, `||` and `??`.
+    pub decision_points: usize,
+    /// Decision points plus one for every function, but not class, and the
+    /// top level code.
+    pub cyclomatic_complexity: usize,
+}
+
+/// Weights of the classes in Chepin's metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChepinWeights {
+    pub p: f32,
+    pub m: f32,
+    pub c: f32,
+    pub t: f32,
+}
+
+impl Default for ChepinWeights {
+    fn default() -> Self {
+        Self {
+            p: 1.0,
+            m: 2.0,
+            c: 3.0,
+            t: 0.5,
+        }
+    }
+}
+
+/// Identifiers of a program split into Chepin's classes.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+pub struct ChepinGroups {
+    /// Input variables.
+    pub p: Vec<String>,
+    /// Modified variables.
+    pub m: Vec<String>,
+    /// Control variables.
+    pub c: Vec<String>,
+    /// Unused variables.
+    pub t: Vec<String>,
+}
+
+impl ChepinGroups {
+    /// Q = a * P + b * M + c * C + d * T
+    pub fn q(&self, weights: &ChepinWeights) -> f32 {
+        weights.p * self.p.len() as f32
+            + weights.m * self.m.len() as f32
+            + weights.c * self.c.len() as f32
+            + weights.t * self.t.len() as f32
+    }
+}
+
+/// Chepin's data complexity of a program.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ChepinMetrics {
+    pub all: ChepinGroups,
+    /// Only the variables passed to input/output functions.
+    pub io: ChepinGroups,
+    pub q: f32,
+    pub io_q: f32,
+}
+
+/// Span of a single identifier, the number of its occurrences after the first one.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct IdentSpan {
+    pub name: String,
+    pub span: usize,
+}
+
+/// Spans of all the identifiers of a program.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SpanReport {
+    /// Sorted from the largest span to the smallest, then by name.
+    pub identifiers: Vec<IdentSpan>,
+    /// Sum of the spans of all identifiers.
+    pub total: usize,
+}
+
+impl SpanReport {
+    pub fn new(mut identifiers: Vec<IdentSpan>) -> Self {
+        identifiers.sort_by(|a, b| b.span.cmp(&a.span).then_with(|| a.name.cmp(&b.name)));
+        let total = identifiers.iter().map(|ident| ident.span).sum();
+        Self { identifiers, total }
+    }
+
+    /// `n` identifiers with the largest span.
+    pub fn top(&self, n: usize) -> &[IdentSpan] {
+        &self.identifiers[..n.min(self.identifiers.len())]
+    }
+
+    pub fn max(&self) -> usize {
+        self.identifiers.first().map_or(0, |ident| ident.span)
+    }
+}
+
+/// Value of a single metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricValue {
+    Int(usize),
+    Float(f32),
+}
+
+impl fmt::Display for MetricValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetricValue::Int(n) => write!(f, "{n}"),
+            MetricValue::Float(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// A single metric of the report, as shown in tables and exports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricRow {
+    /// Stable machine readable name of the metric.
+    pub key: &'static str,
+    /// Human readable name of the metric.
+    pub label: &'static str,
+    pub value: MetricValue,
+}
+
+/// All the metrics computed for a program.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct MetricsReport {
+    pub halstead: HalsteadMetrics,
+    pub djilb: DjilbMetrics,
+    pub mccabe: McCabeMetrics,
+    /// SonarSource's cognitive complexity.
+    pub cognitive_complexity: usize,
+    pub chepin: ChepinMetrics,
+    pub spans: SpanReport,
+}
+
+impl MetricsReport {
+    /// Flattened list of metrics, in display order.
+    pub fn rows(&self) -> Vec<MetricRow> {
+        use MetricValue::*;
+
+        let h = &self.halstead;
+        let d = &self.djilb;
+        let m = &self.mccabe;
+        let c = &self.chepin;
+        let row = |key, label, value| MetricRow { key, label, value };
+
+        vec![
+            row(
+                "unique_operators",
+                "Unique operators",
+                Int(h.unique_operators),
+            ),
+            row("unique_operands", "Unique operands", Int(h.unique_operands)),
+            row("total_operators", "Total operators", Int(h.total_operators)),
+            row("total_operands", "Total operands", Int(h.total_operands)),
+            row("vocabulary", "Program vocabulary", Int(h.vocabulary)),
+            row("length", "Program length", Int(h.length)),
+            row(
+                "estimated_length",
+                "Estimated length",
+                Float(h.estimated_length),
+            ),
+            row("purity_ratio", "Purity ratio", Float(h.purity_ratio)),
+            row("volume", "Program volume", Float(h.volume)),
+            row(
+                "potential_volume",
+                "Potential volume",
+                Float(h.potential_volume),
+            ),
+            row("level", "Program level", Float(h.level)),
+            row("difficulty", "Difficulty", Float(h.difficulty)),
+            row(
+                "intelligence_content",
+                "Intelligence content",
+                Float(h.intelligence_content),
+            ),
+            row("effort", "Effort", Float(h.effort)),
+            row("time", "Time to implement, s", Float(h.time)),
+            row("delivered_bugs", "Delivered bugs", Float(h.delivered_bugs)),
+            row("statements", "Program statements", Int(d.statements)),
+            row(
+                "djilb_if_count",
+                "Djilb CL (amount of if's)",
+                Int(d.if_count),
+            ),
+            row(
+                "djilb_if_saturation",
+                "Djilb cl (if saturation)",
+                Float(d.if_saturation),
+            ),
+            row(
+                "djilb_max_if_depth",
+                "Djilb CLI (max if depth)",
+                Int(d.max_if_depth),
+            ),
+            row("decision_points", "Decision points", Int(m.decision_points)),
+            row(
+                "cyclomatic_complexity",
+                "Cyclomatic complexity",
+                Int(m.cyclomatic_complexity),
+            ),
+            row(
+                "cognitive_complexity",
+                "Cognitive complexity",
+                Int(self.cognitive_complexity),
+            ),
+            row("chepin_p", "Chepin P (input)", Int(c.all.p.len())),
+            row("chepin_m", "Chepin M (modified)", Int(c.all.m.len())),
+            row("chepin_c", "Chepin C (control)", Int(c.all.c.len())),
+            row("chepin_t", "Chepin T (unused)", Int(c.all.t.len())),
+            row("chepin_q", "Chepin Q", Float(c.q)),
+            row("chepin_io_p", "Chepin I/O P", Int(c.io.p.len())),
+            row("chepin_io_m", "Chepin I/O M", Int(c.io.m.len())),
+            row("chepin_io_c", "Chepin I/O C", Int(c.io.c.len())),
+            row("chepin_io_t", "Chepin I/O T", Int(c.io.t.len())),
+            row("chepin_io_q", "Chepin I/O Q", Float(c.io_q)),
+            row("span_total", "Total span", Int(self.spans.total)),
+            row("span_max", "Max span", Int(self.spans.max())),
+        ]
+    }
+
+    /// Value of the metric with given machine readable `key`.
+    pub fn get(&self, key: &str) -> Option<MetricValue> {
+        self.rows()
+            .into_iter()
+            .find(|row| row.key == key)
+            .map(|row| row.value)
+    }
+}
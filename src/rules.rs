@@ -0,0 +1,172 @@
+use rslint_parser::SyntaxKind;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Rules the analyzer counts with unless told otherwise.
+const DEFAULT_PROFILE: &str = include_str!("../rules/default.toml");
+
+/// What a syntax node counts as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeClass {
+    Operator,
+    Operand,
+    /// Not counted, what is inside of it still is.
+    Ignored,
+    /// Neither the node nor anything inside of it is counted, like types.
+    Skipped,
+}
+
+/// How a kind of syntax node is counted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeRule {
+    pub class: NodeClass,
+    /// Name of the operator, by default the operator token of expressions,
+    /// `callee()` of calls or the text of the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Djilb's statements the node adds.
+    #[serde(default)]
+    pub statements: usize,
+}
+
+/// Classification of syntax nodes into operators and operands, loadable
+/// from a TOML or JSON profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CountingRules {
+    pub name: String,
+    /// Rules by rslint_parser node kind, like `IF_STMT`.
+    pub nodes: BTreeMap<String, NodeRule>,
+    #[serde(skip)]
+    by_kind: HashMap<SyntaxKind, NodeRule>,
+}
+
+#[derive(Debug)]
+pub enum RulesError {
+    Io(std::io::Error),
+    Toml(toml_edit::TomlError),
+    Json(serde_json::Error),
+    UnknownExtension(String),
+    UnknownNodeKind(String),
+}
+
+impl std::fmt::Display for RulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Toml(err) => write!(f, "invalid TOML: {err}"),
+            Self::Json(err) => write!(f, "invalid profile: {err}"),
+            Self::UnknownExtension(ext) => {
+                write!(f, "unknown profile format `{ext}`, expected .toml or .json")
+            }
+            Self::UnknownNodeKind(kind) => write!(f, "unknown node kind `{kind}`"),
+        }
+    }
+}
+
+impl std::error::Error for RulesError {}
+
+impl Default for CountingRules {
+    /// Parsed once, every dictionary starts with the default rules.
+    fn default() -> Self {
+        static DEFAULT: OnceLock<CountingRules> = OnceLock::new();
+        DEFAULT
+            .get_or_init(|| Self::from_toml(DEFAULT_PROFILE).expect("Default profile is valid"))
+            .clone()
+    }
+}
+
+impl CountingRules {
+    /// Loads a profile, TOML or JSON depending on the extension of the file.
+    pub fn load(path: &Path) -> Result<Self, RulesError> {
+        let text = std::fs::read_to_string(path).map_err(RulesError::Io)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("json") => Self::from_json(&text),
+            ext => Err(RulesError::UnknownExtension(
+                ext.unwrap_or_default().to_string(),
+            )),
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, RulesError> {
+        let rules = serde_json::from_str(text).map_err(RulesError::Json)?;
+        Self::resolve(rules)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, RulesError> {
+        let document: toml_edit::Document = text.parse().map_err(RulesError::Toml)?;
+        let value = toml_to_json(document.as_item());
+        let rules = serde_json::from_value(value).map_err(RulesError::Json)?;
+        Self::resolve(rules)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Rules are serializable")
+    }
+
+    /// Rule of the node kind, `None` if it is not counted at all.
+    pub fn rule(&self, kind: SyntaxKind) -> Option<&NodeRule> {
+        self.by_kind.get(&kind)
+    }
+
+    /// Looks up the node kinds of the rules.
+    fn resolve(mut rules: Self) -> Result<Self, RulesError> {
+        let kinds: HashMap<String, SyntaxKind> = (0..SyntaxKind::__LAST as u16)
+            .map(SyntaxKind::from)
+            .map(|kind| (format!("{kind:?}"), kind))
+            .collect();
+
+        rules.by_kind = rules
+            .nodes
+            .iter()
+            .map(|(name, rule)| match kinds.get(name) {
+                Some(&kind) => Ok((kind, rule.clone())),
+                None => Err(RulesError::UnknownNodeKind(name.clone())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(rules)
+    }
+}
+
+fn toml_to_json(item: &toml_edit::Item) -> serde_json::Value {
+    use serde_json::Value;
+    match item {
+        toml_edit::Item::None => Value::Null,
+        toml_edit::Item::Value(value) => toml_value_to_json(value),
+        toml_edit::Item::Table(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, item)| (key.to_string(), toml_to_json(item)))
+                .collect(),
+        ),
+        toml_edit::Item::ArrayOfTables(tables) => Value::Array(
+            tables
+                .iter()
+                .map(|table| toml_to_json(&toml_edit::Item::Table(table.clone())))
+                .collect(),
+        ),
+    }
+}
+
+fn toml_value_to_json(value: &toml_edit::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        toml_edit::Value::String(s) => Value::from(s.value().as_str()),
+        toml_edit::Value::Integer(i) => Value::from(*i.value()),
+        toml_edit::Value::Float(f) => Value::from(*f.value()),
+        toml_edit::Value::Boolean(b) => Value::from(*b.value()),
+        toml_edit::Value::Datetime(d) => Value::from(d.value().to_string()),
+        toml_edit::Value::Array(array) => {
+            Value::Array(array.iter().map(toml_value_to_json).collect())
+        }
+        toml_edit::Value::InlineTable(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), toml_value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,303 @@
+//! SARIF 2.1.0 log of metrics above their thresholds, for code scanning
+//! dashboards.
+
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::metrics::{Dictionary, FunctionKind, FunctionMetrics};
+use crate::report::{MetricValue, MetricsReport};
+use crate::span::Span;
+
+/// Upper limit of a metric, anything above it is reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    /// Machine readable key of the metric, as in `MetricsReport::rows`.
+    pub metric: String,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThresholdError {
+    /// Not `metric=value`.
+    Invalid(String),
+    UnknownMetric(String),
+}
+
+impl std::fmt::Display for ThresholdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(text) => write!(f, "invalid threshold `{text}`, expected `metric=value`"),
+            Self::UnknownMetric(metric) => write!(f, "unknown metric `{metric}`"),
+        }
+    }
+}
+
+impl std::error::Error for ThresholdError {}
+
+impl std::str::FromStr for Threshold {
+    type Err = ThresholdError;
+
+    /// Parses `metric=value`, like `effort=5000`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || ThresholdError::Invalid(text.to_string());
+        let (metric, max) = text.split_once('=').ok_or_else(invalid)?;
+        let metric = metric.trim();
+        let max = max.trim().parse().map_err(|_| invalid())?;
+        if MetricsReport::default().get(metric).is_none() {
+            return Err(ThresholdError::UnknownMetric(metric.to_string()));
+        }
+        Ok(Self {
+            metric: metric.to_string(),
+            max,
+        })
+    }
+}
+
+fn as_f64(value: MetricValue) -> f64 {
+    match value {
+        MetricValue::Int(n) => n as f64,
+        MetricValue::Float(x) => f64::from(x),
+    }
+}
+
+/// URI reference of the file, relative paths stay relative to the root of
+/// the sources, absolute ones become `file://` URIs.
+pub fn file_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    let mut uri = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            /* `C:` of Windows paths */
+            b':' if uri.len() == 1 => uri.push(':'),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    if Path::new(&path).is_absolute() || uri.as_bytes().get(1) == Some(&b':') {
+        let slash = if uri.starts_with('/') { "" } else { "/" };
+        format!("file://{slash}{uri}")
+    } else {
+        uri
+    }
+}
+
+/// Physical location of the `region` of the file, and the function it is
+/// in if any.
+fn location(path: &str, region: Span, function: Option<&FunctionMetrics>) -> Value {
+    let uri = file_uri(path);
+    let mut artifact = json!({ "uri": uri });
+    if !uri.starts_with("file://") {
+        artifact["uriBaseId"] = json!("%SRCROOT%");
+    }
+    let mut location = json!({
+        "physicalLocation": {
+            "artifactLocation": artifact,
+            "region": {
+                "startLine": region.line,
+                "startColumn": region.column,
+                "endLine": region.end_line,
+                "endColumn": region.end_column,
+            },
+        },
+    });
+    if let Some(function) = function {
+        location["logicalLocations"] = json!([{
+            "name": function.name,
+            "kind": if function.kind == FunctionKind::Class { "type" } else { "function" },
+        }]);
+    }
+    location
+}
+
+/// SARIF log with a result for every metric of the files, or of their
+/// functions, above its threshold. Every metric with a threshold is a rule,
+/// identified by the key of the metric, the last threshold of a metric
+/// given more than once wins.
+pub fn to_sarif<'a>(
+    files: impl IntoIterator<Item = (&'a str, &'a Dictionary)>,
+    thresholds: &[Threshold],
+) -> String {
+    let labels: Vec<_> = MetricsReport::default()
+        .rows()
+        .into_iter()
+        .map(|row| (row.key, row.label))
+        .collect();
+    let label = |metric: &str| {
+        labels
+            .iter()
+            .find(|(key, _)| *key == metric)
+            .map_or(metric.to_string(), |(_, label)| label.to_string())
+    };
+
+    let mut unique: Vec<&Threshold> = Vec::new();
+    for threshold in thresholds {
+        match unique.iter_mut().find(|t| t.metric == threshold.metric) {
+            Some(t) => *t = threshold,
+            None => unique.push(threshold),
+        }
+    }
+    let thresholds = unique;
+
+    let rules: Vec<_> = thresholds
+        .iter()
+        .map(|threshold| {
+            json!({
+                "id": threshold.metric,
+                "shortDescription": {
+                    "text": format!("{} above {}", label(&threshold.metric), threshold.max),
+                },
+                "defaultConfiguration": { "level": "warning" },
+                "properties": { "threshold": threshold.max },
+            })
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for (path, dict) in files {
+        let units = std::iter::once((None, dict.source_span(), dict))
+            .chain(dict.functions.iter().map(|f| (Some(f), f.span, &f.dict)));
+        for (function, span, unit_dict) in units {
+            let report = &unit_dict.report;
+            for (index, threshold) in thresholds.iter().enumerate() {
+                let Some(value) = report.get(&threshold.metric) else {
+                    continue;
+                };
+                if as_f64(value) <= threshold.max {
+                    continue;
+                }
+                let region = match threshold.metric.as_str() {
+                    "djilb_max_if_depth" => unit_dict.deepest_if.unwrap_or(span),
+                    _ => span,
+                };
+                let unit = match function {
+                    None => "the file".to_string(),
+                    Some(f) => format!("{} `{}`", f.kind.name(), f.name),
+                };
+                results.push(json!({
+                    "ruleId": threshold.metric,
+                    "ruleIndex": index,
+                    "level": "warning",
+                    "message": {
+                        "text": format!(
+                            "{} of {unit} is {value}, above {}",
+                            label(&threshold.metric),
+                            threshold.max
+                        ),
+                    },
+                    "locations": [location(path, region, function)],
+                }));
+            }
+        }
+    }
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "halstead_metrics",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            /* Columns of spans are counted in characters */
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).expect("SARIF log is serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::process_js;
+
+    #[test]
+    fn sarif_violations() {
+        assert_eq!(file_uri("./src/a b.js"), "src/a%20b.js");
+        assert_eq!(file_uri("/home/ä.js"), "file:///home/%C3%A4.js");
+        assert_eq!(file_uri("C:\\src\\a.js"), "file:///C:/src/a.js");
+        assert_eq!(
+            "effort".parse::<Threshold>(),
+            Err(ThresholdError::Invalid("effort".to_string()))
+        );
+        assert_eq!(
+            "nope=1".parse::<Threshold>(),
+            Err(ThresholdError::UnknownMetric("nope".to_string()))
+        );
+
+        let dict = process_js("x = 1;\nfunction f(a) {\n  if (a) { if (a > 1) { return 2; } }\n}")
+            .unwrap();
+        /* The last threshold of the volume wins */
+        let thresholds: Vec<Threshold> = ["volume=1000", "djilb_max_if_depth=1", "volume=10"]
+            .iter()
+            .map(|t| t.parse().unwrap())
+            .collect();
+        let log: Value =
+            serde_json::from_str(&to_sarif([("src/a b.js", &dict)], &thresholds)).unwrap();
+
+        assert_eq!(log["version"], "2.1.0");
+        let rules = log["runs"][0]["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0]["id"], "volume");
+        assert_eq!(rules[0]["properties"]["threshold"], 10.0);
+        assert_eq!(rules[1]["id"], "djilb_max_if_depth");
+
+        let region = |result: &Value| {
+            let location = &result["locations"][0]["physicalLocation"];
+            assert_eq!(location["artifactLocation"]["uri"], "src/a%20b.js");
+            let region = &location["region"];
+            [
+                region["startLine"].as_u64(),
+                region["startColumn"].as_u64(),
+                region["endLine"].as_u64(),
+                region["endColumn"].as_u64(),
+            ]
+            .map(Option::unwrap)
+        };
+
+        /* Volume and depth 2 of the file and of `f` */
+        let results = log["runs"][0]["results"].as_array().unwrap();
+        let rule_ids: Vec<_> = results
+            .iter()
+            .map(|r| r["ruleId"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            rule_ids,
+            [
+                "volume",
+                "djilb_max_if_depth",
+                "volume",
+                "djilb_max_if_depth"
+            ]
+        );
+        /* The whole file, `f`, and the inner `if` for both depths */
+        assert_eq!(region(&results[0]), [1, 1, 4, 2]);
+        assert_eq!(region(&results[1]), [3, 12, 3, 36]);
+        assert_eq!(region(&results[2]), [2, 1, 4, 2]);
+        assert_eq!(region(&results[3]), [3, 12, 3, 36]);
+        assert!(results[1]["locations"][0].get("logicalLocations").is_none());
+        assert_eq!(
+            results[3]["locations"][0]["logicalLocations"][0]["name"],
+            "f"
+        );
+        assert_eq!(
+            results[3]["message"]["text"],
+            "Djilb CLI (max if depth) of function `f` is 2, above 1"
+        );
+
+        /* The `if` itself, not the comment before it */
+        let dict = process_js("if (a) {\n  // deeper\n  if (b) { c(); }\n}").unwrap();
+        let thresholds = ["djilb_max_if_depth=1".parse().unwrap()];
+        let log: Value =
+            serde_json::from_str(&to_sarif([("src/a b.js", &dict)], &thresholds)).unwrap();
+        assert_eq!(region(&log["runs"][0]["results"][0]), [3, 3, 3, 18]);
+    }
+}
@@ -0,0 +1,342 @@
+use rslint_parser::{ast, AstNode, SyntaxKind, SyntaxNode, SyntaxNodeExt};
+use std::collections::HashMap;
+
+use crate::span::LineIndex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    /// Script or module.
+    Global,
+    Function,
+    Block,
+    Catch,
+    Class,
+}
+
+#[derive(Debug)]
+struct Scope {
+    kind: ScopeKind,
+    parent: Option<usize>,
+    bindings: HashMap<String, usize>,
+}
+
+/// A variable, function, class or parameter declared in some scope.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub scope: ScopeKind,
+    /// Byte offset of the declaring name, `None` for undeclared globals.
+    pub declared_at: Option<usize>,
+    /// Name that tells apart bindings of the same name, `name@line:column`
+    /// of the declaration if there are several of them in the program.
+    pub key: String,
+}
+
+/// Scopes of a program and the bindings every identifier refers to.
+#[derive(Default, Debug)]
+pub struct ScopeTree {
+    scopes: Vec<Scope>,
+    bindings: Vec<Binding>,
+    /// Binding of every `Name` and `NameRef` which is a variable, by its offset.
+    names: HashMap<usize, usize>,
+}
+
+fn offset(node: &SyntaxNode) -> usize {
+    usize::from(node.text_range().start())
+}
+
+fn is_function(kind: SyntaxKind) -> bool {
+    use SyntaxKind::*;
+    matches!(
+        kind,
+        FN_DECL | FN_EXPR | ARROW_EXPR | METHOD | GETTER | SETTER | CONSTRUCTOR
+    )
+}
+
+/// TypeScript types, their parameters and property names are not variables.
+fn is_type(kind: SyntaxKind) -> bool {
+    use SyntaxKind::*;
+    matches!(
+        kind,
+        TS_INTERFACE_DECL
+            | TS_TYPE_ALIAS_DECL
+            | TS_TYPE_PARAMS
+            | TS_TYPE_ARGS
+            | TS_FN_TYPE
+            | TS_CONSTRUCTOR_TYPE
+            | TS_OBJECT_TYPE
+            | TS_MAPPED_TYPE
+            | TS_INDEX_SIGNATURE
+    )
+}
+
+impl ScopeTree {
+    pub fn build(root: &SyntaxNode, lines: &LineIndex) -> Self {
+        let mut builder = Builder::default();
+        let global = builder.add_scope(ScopeKind::Global, None);
+        builder.visit(root, global);
+        builder.resolve_references(global);
+
+        let mut tree = builder.tree;
+        tree.assign_keys(lines);
+        tree
+    }
+
+    /// Binding the `Name` or `NameRef` refers to, `None` for property names and labels.
+    pub fn binding(&self, name: &SyntaxNode) -> Option<&Binding> {
+        if !matches!(name.kind(), SyntaxKind::NAME | SyntaxKind::NAME_REF) {
+            return None;
+        }
+        self.names.get(&offset(name)).map(|&i| &self.bindings[i])
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    fn assign_keys(&mut self, lines: &LineIndex) {
+        let mut same_name: HashMap<&str, usize> = HashMap::new();
+        for binding in &self.bindings {
+            *same_name.entry(&binding.name).or_default() += 1;
+        }
+
+        let keys: Vec<String> = self
+            .bindings
+            .iter()
+            .map(|binding| match binding.declared_at {
+                Some(at) if same_name[binding.name.as_str()] > 1 => {
+                    let (line, column) = lines.line_col(at);
+                    format!("{}@{line}:{column}", binding.name)
+                }
+                _ => binding.name.clone(),
+            })
+            .collect();
+
+        for (binding, key) in self.bindings.iter_mut().zip(keys) {
+            binding.key = key;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    tree: ScopeTree,
+    /// Offset, name and scope of every identifier usage.
+    references: Vec<(usize, String, usize)>,
+}
+
+impl Builder {
+    fn add_scope(&mut self, kind: ScopeKind, parent: Option<usize>) -> usize {
+        self.tree.scopes.push(Scope {
+            kind,
+            parent,
+            bindings: HashMap::new(),
+        });
+        self.tree.scopes.len() - 1
+    }
+
+    /// Closest function or global scope, where `var`s are hoisted to.
+    fn var_scope(&self, mut scope: usize) -> usize {
+        loop {
+            let Scope { kind, parent, .. } = &self.tree.scopes[scope];
+            match (kind, parent) {
+                (ScopeKind::Function | ScopeKind::Global, _) | (_, None) => return scope,
+                (_, Some(parent)) => scope = *parent,
+            }
+        }
+    }
+
+    fn declare(&mut self, scope: usize, name: &SyntaxNode) {
+        let text = name.text().to_string();
+        let binding = match self.tree.scopes[scope].bindings.get(&text) {
+            /* `var i` redeclared in the same function */
+            Some(&binding) => binding,
+            None => {
+                self.tree.bindings.push(Binding {
+                    name: text.clone(),
+                    scope: self.tree.scopes[scope].kind,
+                    declared_at: Some(offset(name)),
+                    key: String::new(),
+                });
+                let binding = self.tree.bindings.len() - 1;
+                self.tree.scopes[scope].bindings.insert(text, binding);
+                binding
+            }
+        };
+        self.tree.names.insert(offset(name), binding);
+    }
+
+    /// Declares every name bound by a (possibly destructuring) pattern.
+    fn declare_pattern(&mut self, scope: usize, pattern: &SyntaxNode) {
+        use SyntaxKind::*;
+        match pattern.kind() {
+            NAME => self.declare(scope, pattern),
+            SINGLE_PATTERN => {
+                if let Some(name) = pattern.children().find(|c| c.kind() == NAME) {
+                    self.declare(scope, &name);
+                }
+            }
+            ARRAY_PATTERN | OBJECT_PATTERN | REST_PATTERN => {
+                for child in pattern.children() {
+                    self.declare_pattern(scope, &child);
+                }
+            }
+            /* Default value is an expression, not a part of the pattern */
+            ASSIGN_PATTERN => {
+                if let Some(target) = pattern.first_child() {
+                    self.declare_pattern(scope, &target);
+                }
+            }
+            /* `key: pattern`, the key is a property name */
+            KEY_VALUE_PATTERN => {
+                if let Some(value) = pattern.children().nth(1) {
+                    self.declare_pattern(scope, &value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit(&mut self, node: &SyntaxNode, scope: usize) {
+        use SyntaxKind::*;
+
+        let mut inner = scope;
+        match node.kind() {
+            kind if is_type(kind) => return,
+            kind if is_function(kind) => {
+                inner = self.add_scope(ScopeKind::Function, Some(scope));
+                let name = node.children().find(|c| c.kind() == NAME);
+                match (kind, name) {
+                    (FN_DECL, Some(name)) => self.declare(scope, &name),
+                    /* Name of a function expression is only visible inside of it */
+                    (FN_EXPR, Some(name)) => self.declare(inner, &name),
+                    /* `x => x` */
+                    (ARROW_EXPR, Some(name)) => self.declare(inner, &name),
+                    _ => {}
+                }
+                for params in node
+                    .children()
+                    .filter(|c| matches!(c.kind(), PARAMETER_LIST | CONSTRUCTOR_PARAMETERS))
+                {
+                    for param in params.children() {
+                        self.declare_pattern(inner, &param);
+                    }
+                }
+            }
+            CLASS_DECL | CLASS_EXPR => {
+                inner = self.add_scope(ScopeKind::Class, Some(scope));
+                if let Some(name) = node.children().find(|c| c.kind() == NAME) {
+                    let target = if node.kind() == CLASS_DECL {
+                        scope
+                    } else {
+                        inner
+                    };
+                    self.declare(target, &name);
+                }
+            }
+            TS_ENUM => {
+                if let Some(name) = node.children().find(|c| c.kind() == NAME) {
+                    self.declare(scope, &name);
+                }
+            }
+            /* Body of a function shares the scope with its parameters */
+            BLOCK_STMT if !node.parent().is_some_and(|p| is_function(p.kind())) => {
+                inner = self.add_scope(ScopeKind::Block, Some(scope));
+            }
+            FOR_STMT | FOR_IN_STMT | FOR_OF_STMT | SWITCH_STMT => {
+                inner = self.add_scope(ScopeKind::Block, Some(scope));
+            }
+            CATCH_CLAUSE => {
+                inner = self.add_scope(ScopeKind::Catch, Some(scope));
+                if let Some(pattern) = node.to::<ast::CatchClause>().error() {
+                    self.declare_pattern(inner, pattern.syntax());
+                }
+            }
+            VAR_DECL => {
+                let decl = node.to::<ast::VarDecl>();
+                let target = if decl.is_var() {
+                    self.var_scope(scope)
+                } else {
+                    scope
+                };
+                for declarator in decl.declared() {
+                    if let Some(pattern) = declarator.pattern() {
+                        self.declare_pattern(target, pattern.syntax());
+                    }
+                }
+            }
+            IMPORT_DECL => {
+                let global = self.var_scope(scope);
+                for child in node.descendants().skip(1) {
+                    let is_local_name = child.kind() == NAME
+                        && child.parent().is_none_or(|parent| {
+                            /* `imported as local` */
+                            parent.kind() != SPECIFIER
+                                || parent.last_child().as_ref() == Some(&child)
+                        });
+                    if is_local_name {
+                        self.declare(global, &child);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        match node.kind() {
+            /* Labels of `break` and `continue` are not variables */
+            NAME_REF
+                if !node
+                    .parent()
+                    .is_some_and(|p| matches!(p.kind(), BREAK_STMT | CONTINUE_STMT)) =>
+            {
+                self.references
+                    .push((offset(node), node.text().to_string(), scope));
+            }
+            /* Shorthand `{ name }` object property, or a name in the pattern
+             * of a destructuring assignment, `[a, b] = [b, a]` */
+            NAME if node.parent().is_some_and(|p| {
+                p.kind() == IDENT_PROP
+                    || (p.kind() == SINGLE_PATTERN && !self.tree.names.contains_key(&offset(node)))
+            }) =>
+            {
+                self.references
+                    .push((offset(node), node.text().to_string(), scope));
+            }
+            _ => {}
+        }
+
+        for child in node.children() {
+            self.visit(&child, inner);
+        }
+    }
+
+    /// Resolves every usage to the closest binding of the same name, after
+    /// all the declarations are known, so hoisting comes for free.
+    fn resolve_references(&mut self, global: usize) {
+        for (at, name, scope) in std::mem::take(&mut self.references) {
+            let mut scope = Some(scope);
+            let mut binding = None;
+            while let Some(s) = scope {
+                binding = self.tree.scopes[s].bindings.get(&name).copied();
+                if binding.is_some() {
+                    break;
+                }
+                scope = self.tree.scopes[s].parent;
+            }
+
+            /* Undeclared variables are implicitly global */
+            let binding = binding.unwrap_or_else(|| {
+                self.tree.bindings.push(Binding {
+                    name: name.clone(),
+                    scope: ScopeKind::Global,
+                    declared_at: None,
+                    key: String::new(),
+                });
+                let binding = self.tree.bindings.len() - 1;
+                self.tree.scopes[global].bindings.insert(name, binding);
+                binding
+            });
+            self.tree.names.insert(at, binding);
+        }
+    }
+}
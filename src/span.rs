@@ -0,0 +1,71 @@
+use rslint_parser::TextRange;
+use serde::Serialize;
+
+/// Location of a piece of source code.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Span {
+    /// Byte offset of the first character.
+    pub start: usize,
+    /// Byte offset just past the last character.
+    pub end: usize,
+    /// 1-based line of the first character.
+    pub line: usize,
+    /// 1-based column of the first character, counted in characters.
+    pub column: usize,
+    /// 1-based line of the last character.
+    pub end_line: usize,
+    /// 1-based column just past the last character, counted in characters.
+    pub end_column: usize,
+}
+
+/// Converts byte offsets of a source text into lines and columns.
+#[derive(Default, Debug)]
+pub struct LineIndex {
+    text: String,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            text: text.to_string(),
+            line_starts,
+        }
+    }
+
+    /// 1-based line and column of the byte `offset`.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line = line.max(1);
+        let line_start = self.line_starts.get(line - 1).copied().unwrap_or(0);
+        let column = self
+            .text
+            .get(line_start..offset)
+            .map_or(offset - line_start, |s| s.chars().count());
+        (line, column + 1)
+    }
+
+    /// Span of the whole text.
+    pub fn whole(&self) -> Span {
+        self.span(TextRange::new(0.into(), (self.text.len() as u32).into()))
+    }
+
+    pub fn span(&self, range: TextRange) -> Span {
+        let start = usize::from(range.start());
+        let end = usize::from(range.end());
+        let (line, column) = self.line_col(start);
+        let (end_line, end_column) = self.line_col(end);
+        Span {
+            start,
+            end,
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+}
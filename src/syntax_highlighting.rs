@@ -1,4 +1,5 @@
 use egui::text::LayoutJob;
+use halstead_metrics::html::SyntectTheme;
 
 /// View some code with syntax highlighting and selection.
 pub fn code_view_ui(ui: &mut egui::Ui, mut code: &str) {
@@ -38,67 +39,67 @@ pub fn highlight(ctx: &egui::Context, theme: &CodeTheme, code: &str, language: &
     })
 }
 
-#[derive(Clone, Copy, Hash, PartialEq)]
-#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-enum SyntectTheme {
-    Base16EightiesDark,
-    Base16MochaDark,
-    Base16OceanDark,
-    Base16OceanLight,
-    InspiredGitHub,
-    SolarizedDark,
-    SolarizedLight,
-}
-
-impl SyntectTheme {
-    fn all() -> impl ExactSizeIterator<Item = Self> {
-        [
-            Self::Base16EightiesDark,
-            Self::Base16MochaDark,
-            Self::Base16OceanDark,
-            Self::Base16OceanLight,
-            Self::InspiredGitHub,
-            Self::SolarizedDark,
-            Self::SolarizedLight,
-        ]
-        .iter()
-        .copied()
-    }
-
-    fn name(&self) -> &'static str {
-        match self {
-            Self::Base16EightiesDark => "Base16 Eighties (dark)",
-            Self::Base16MochaDark => "Base16 Mocha (dark)",
-            Self::Base16OceanDark => "Base16 Ocean (dark)",
-            Self::Base16OceanLight => "Base16 Ocean (light)",
-            Self::InspiredGitHub => "InspiredGitHub (light)",
-            Self::SolarizedDark => "Solarized (dark)",
-            Self::SolarizedLight => "Solarized (light)",
-        }
+/// Paints the background of byte ranges of a highlighted `job`, splitting
+/// its sections where needed. Later marks are painted over earlier ones.
+pub fn mark_ranges(job: &mut LayoutJob, marks: &[(std::ops::Range<usize>, egui::Color32)]) {
+    if marks.is_empty() {
+        return;
     }
 
-    fn syntect_key_name(&self) -> &'static str {
-        match self {
-            Self::Base16EightiesDark => "base16-eighties.dark",
-            Self::Base16MochaDark => "base16-mocha.dark",
-            Self::Base16OceanDark => "base16-ocean.dark",
-            Self::Base16OceanLight => "base16-ocean.light",
-            Self::InspiredGitHub => "InspiredGitHub",
-            Self::SolarizedDark => "Solarized (dark)",
-            Self::SolarizedLight => "Solarized (light)",
+    let mut sections = Vec::with_capacity(job.sections.len());
+    for section in job.sections.drain(..) {
+        let range = section.byte_range.clone();
+        let mut cuts: Vec<usize> = marks
+            .iter()
+            .flat_map(|(mark, _)| [mark.start, mark.end])
+            .filter(|cut| range.contains(cut))
+            .chain([range.start, range.end])
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        for piece in cuts.windows(2) {
+            let mut part = section.clone();
+            part.byte_range = piece[0]..piece[1];
+            if piece[0] != range.start {
+                part.leading_space = 0.0;
+            }
+            if let Some((_, color)) = marks
+                .iter()
+                .rev()
+                .find(|(mark, _)| mark.start <= piece[0] && piece[1] <= mark.end)
+            {
+                part.format.background = *color;
+            }
+            sections.push(part);
         }
     }
+    job.sections = sections;
+}
 
-    pub fn is_dark(&self) -> bool {
-        match self {
-            Self::Base16EightiesDark
-            | Self::Base16MochaDark
-            | Self::Base16OceanDark
-            | Self::SolarizedDark => true,
-
-            Self::Base16OceanLight | Self::InspiredGitHub | Self::SolarizedLight => false,
-        }
-    }
+/// Paints a wavy line from `from` to `to`, under code with an error.
+pub fn paint_squiggle(
+    painter: &egui::Painter,
+    from: egui::Pos2,
+    to: egui::Pos2,
+    color: egui::Color32,
+) {
+    const HALF_WAVE: f32 = 2.0;
+    const AMPLITUDE: f32 = 1.5;
+
+    let waves = ((to.x - from.x) / HALF_WAVE).ceil().max(1.0) as usize;
+    let points = (0..=waves)
+        .map(|i| {
+            let x = (from.x + i as f32 * HALF_WAVE).min(to.x.max(from.x + HALF_WAVE));
+            let y = if i % 2 == 0 {
+                from.y
+            } else {
+                from.y - AMPLITUDE
+            };
+            egui::pos2(x, y)
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
 }
 
 #[derive(Clone, Hash, PartialEq)]
@@ -162,6 +163,10 @@ impl CodeTheme {
         }
     }
 
+    pub fn syntect_theme(&self) -> SyntectTheme {
+        self.syntect_theme
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui) {
         egui::widgets::global_dark_light_mode_buttons(ui);
 